@@ -1,3 +1,4 @@
+use crate::datatype::DataType;
 use std::collections::HashMap;
 
 pub fn calculate_entropy(values: &[u8]) -> f64 {
@@ -102,3 +103,281 @@ impl PositionStats {
         }
     }
 }
+
+/// Plausible timestamp range for detection: 1990-01-01 to 2040-01-01
+const PLAUSIBLE_SECS: std::ops::Range<i64> = 631_152_000..2_208_988_800;
+
+/// Encodings tried when looking for timestamps
+const TIMESTAMP_TYPES: &[DataType] = &[
+    DataType::Unix32,
+    DataType::UnixMillis,
+    DataType::FileTime,
+    DataType::DosDateTime,
+];
+
+/// A position whose values decode to plausible dates across records
+pub struct TimestampCandidate {
+    pub position: usize,
+    pub data_type: DataType,
+    pub matches: usize,
+    pub total: usize,
+    /// Encoded bytes of the earliest and latest matching values
+    pub earliest: Vec<u8>,
+    pub latest: Vec<u8>,
+    /// Seconds between earliest and latest value
+    span: i64,
+}
+
+/// Find positions where at least `min_pct`% of the records that reach them
+/// hold a 4- or 8-byte value in the plausible date range.
+///
+/// `Unix64` and `GpsTime` aren't tried: a plausible `Unix64` is a `Unix32`
+/// followed by four zero bytes, and GPS seconds are Unix seconds shifted by
+/// about a decade, so either would mostly repeat the `Unix32` candidate at the
+/// same position with the same spread.
+pub fn find_timestamps(
+    records: &[&Vec<u8>],
+    max_positions: usize,
+    min_pct: usize,
+) -> Vec<TimestampCandidate> {
+    let mut candidates = Vec::new();
+
+    for pos in 0..max_positions {
+        for &dtype in TIMESTAMP_TYPES {
+            let size = dtype.byte_size().unwrap_or(4);
+            let values: Vec<&[u8]> = records
+                .iter()
+                .filter_map(|r| r.get(pos..pos + size))
+                .collect();
+            if values.is_empty() {
+                continue;
+            }
+
+            let mut in_range: Vec<(i64, &[u8])> = values
+                .iter()
+                .filter_map(|v| dtype.timestamp(v).map(|(secs, _)| (secs, *v)))
+                .filter(|(secs, _)| PLAUSIBLE_SECS.contains(secs))
+                .collect();
+
+            if in_range.len() * 100 < values.len() * min_pct {
+                continue;
+            }
+
+            in_range.sort_by_key(|(secs, _)| *secs);
+            let span = in_range[in_range.len() - 1].0 - in_range[0].0;
+            candidates.push(TimestampCandidate {
+                position: pos,
+                data_type: dtype,
+                matches: in_range.len(),
+                total: values.len(),
+                earliest: in_range[0].1.to_vec(),
+                latest: in_range[in_range.len() - 1].1.to_vec(),
+                span,
+            });
+        }
+    }
+
+    // A real timestamp also makes its neighbouring offsets look plausible, but
+    // with a much wider spread; keep the tightest of any overlapping candidates
+    let overlaps = |a: &TimestampCandidate, b: &TimestampCandidate| {
+        let end = |c: &TimestampCandidate| c.position + c.data_type.byte_size().unwrap_or(4);
+        a.position < end(b) && b.position < end(a)
+    };
+    let keep: Vec<bool> = candidates
+        .iter()
+        .map(|c| {
+            !candidates
+                .iter()
+                .any(|o| overlaps(c, o) && (o.span, o.position) < (c.span, c.position))
+        })
+        .collect();
+    candidates
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(c, keep)| keep.then_some(c))
+        .collect()
+}
+
+/// Byte distribution expected of the plaintext under an XOR key
+pub struct PlaintextModel {
+    /// Natural log of each byte's probability
+    log_probs: [f64; 256],
+}
+
+/// Relative frequency of 'a'..='z' in English text, per mille
+const LETTER_FREQUENCY: [f64; 26] = [
+    82.0, 15.0, 28.0, 43.0, 127.0, 22.0, 20.0, 61.0, 70.0, 2.0, 8.0, 40.0, 24.0, 67.0, 75.0, 19.0,
+    1.0, 60.0, 63.0, 91.0, 28.0, 10.0, 24.0, 2.0, 20.0, 1.0,
+];
+
+impl PlaintextModel {
+    /// Mostly zero bytes, as in padded binary structures
+    pub fn zeros() -> Self {
+        let mut weights = [0.0; 256];
+        weights[0] = 255.0;
+        Self::from_weights(&weights)
+    }
+
+    /// English-like ASCII text
+    pub fn text() -> Self {
+        let mut weights = [0.0; 256];
+        weights[0x20..0x7f].fill(10.0);
+        for (i, &freq) in LETTER_FREQUENCY.iter().enumerate() {
+            weights[b'a' as usize + i] = freq * 10.0;
+            weights[b'A' as usize + i] = freq;
+        }
+        weights[b' ' as usize] = 1800.0;
+        weights[b'\n' as usize] = 100.0;
+        for b in b".,'\"" {
+            weights[*b as usize] = 100.0;
+        }
+        Self::from_weights(&weights)
+    }
+
+    /// Byte frequencies of a sample of similar plaintext
+    pub fn from_sample(sample: &[u8]) -> Self {
+        let mut weights = [0.0; 256];
+        for (byte, count) in byte_frequency(sample) {
+            weights[byte as usize] = count as f64;
+        }
+        Self::from_weights(&weights)
+    }
+
+    /// Every byte gets one extra count, so none is ruled out entirely
+    fn from_weights(weights: &[f64; 256]) -> Self {
+        let total: f64 = weights.iter().sum::<f64>() + 256.0;
+        Self {
+            log_probs: weights.map(|w| ((w + 1.0) / total).ln()),
+        }
+    }
+
+    /// Log-likelihood of bytes with these frequencies decrypting to this model under `key`
+    fn score(&self, frequency: &HashMap<u8, usize>, key: u8) -> f64 {
+        frequency
+            .iter()
+            .map(|(&b, &count)| count as f64 * self.log_probs[(b ^ key) as usize])
+            .sum()
+    }
+}
+
+/// Combined byte frequencies of the positions a key byte covers: `column`,
+/// `column + len`, `column + 2 * len`, ... with the key restarting each record
+fn key_column(stats: &[PositionStats], len: usize, column: usize) -> HashMap<u8, usize> {
+    let mut frequency = HashMap::new();
+    for position in stats.iter().skip(column).step_by(len) {
+        for (&b, &count) in &position.frequency {
+            *frequency.entry(b).or_insert(0) += count;
+        }
+    }
+    frequency
+}
+
+/// Score each repeating-key length up to `max_len` by the index of coincidence
+/// of the bytes each key position covers, from per-position stats starting at
+/// position 0. Bytes XORed with the same key byte keep the plaintext's uneven
+/// distribution, so the true length (and its multiples) scores well above the
+/// 1/256 of random bytes.
+pub fn xor_key_lengths(stats: &[PositionStats], max_len: usize) -> Vec<(usize, f64)> {
+    (1..=max_len.min(stats.len()))
+        .map(|len| {
+            let (mut pairs, mut same) = (0.0, 0.0);
+            for column in 0..len {
+                let frequency = key_column(stats, len, column);
+                let n: usize = frequency.values().sum();
+                pairs += (n * n.saturating_sub(1)) as f64;
+                same += (frequency.values())
+                    .map(|&c| (c * c.saturating_sub(1)) as f64)
+                    .sum::<f64>();
+            }
+            (len, if pairs > 0.0 { same / pairs } else { 0.0 })
+        })
+        .collect()
+}
+
+/// The shortest key length scoring within 10% of the best, since multiples of
+/// the true length score about as well
+pub fn best_xor_key_length(scores: &[(usize, f64)]) -> Option<usize> {
+    let best = scores.iter().map(|(_, score)| *score).fold(0.0, f64::max);
+    (scores.iter())
+        .find(|(_, score)| *score > 0.0 && *score >= best * 0.9)
+        .map(|(len, _)| *len)
+}
+
+/// The most likely key byte at each position of a `len`-byte repeating key
+pub fn guess_xor_key(stats: &[PositionStats], len: usize, model: &PlaintextModel) -> Vec<u8> {
+    (0..len)
+        .map(|column| {
+            let frequency = key_column(stats, len, column);
+            (0..=255u8)
+                .max_by(|&a, &b| {
+                    let (a, b) = (model.score(&frequency, a), model.score(&frequency, b));
+                    a.total_cmp(&b)
+                })
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guess_xor_key() {
+        let key = b"\x4b\x21\xa0";
+        let encrypt = |plain: &[u8]| -> Vec<u8> {
+            (plain.iter().enumerate())
+                .map(|(i, b)| b ^ key[i % key.len()])
+                .collect()
+        };
+        let stats_of = |records: &[Vec<u8>]| -> Vec<PositionStats> {
+            let refs: Vec<&Vec<u8>> = records.iter().collect();
+            let len = records.iter().map(Vec::len).max().unwrap_or(0);
+            (0..len)
+                .filter_map(|pos| PositionStats::from_records(&refs, pos))
+                .collect()
+        };
+
+        // Sparse binary records: mostly zeros with a few varying bytes
+        let records: Vec<Vec<u8>> = (0..50u8)
+            .map(|i| {
+                let mut plain = vec![0u8; 40];
+                plain[1] = i;
+                plain[7] = i.wrapping_mul(37);
+                encrypt(&plain)
+            })
+            .collect();
+        let stats = stats_of(&records);
+        let len = best_xor_key_length(&xor_key_lengths(&stats, 16)).unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(guess_xor_key(&stats, len, &PlaintextModel::zeros()), key);
+
+        // One record of English text
+        let text = b"the quick brown fox jumps over the lazy dog and then it rests in the \
+                     shade of a tree near the river while the sun goes down over the hills";
+        let stats = stats_of(&[encrypt(text)]);
+        assert_eq!(guess_xor_key(&stats, 3, &PlaintextModel::text()), key);
+    }
+
+    #[test]
+    fn test_find_timestamps() {
+        // A constant header, a minute-by-minute Unix32 timestamp, then padding
+        let records: Vec<Vec<u8>> = (0..20u32)
+            .map(|i| {
+                let mut record = vec![1, 2, 3, 4];
+                record.extend((1_700_000_000 + i * 60).to_le_bytes());
+                record.extend([0; 8]);
+                record
+            })
+            .collect();
+        let refs: Vec<&Vec<u8>> = records.iter().collect();
+
+        // Offset 3 also reads as a plausible date, but spread 256 times wider
+        let found = find_timestamps(&refs, 12, 90);
+        let at: Vec<_> = found.iter().map(|c| (c.position, c.data_type)).collect();
+        assert_eq!(at, [(4, DataType::Unix32)]);
+        assert_eq!(found[0].matches, 20);
+        assert_eq!(found[0].earliest, 1_700_000_000u32.to_le_bytes());
+        assert_eq!(found[0].latest, 1_700_001_140u32.to_le_bytes());
+    }
+}
@@ -0,0 +1,277 @@
+//! Gloss result cache
+//!
+//! Successful gloss results are kept in memory for the current run, dropping
+//! the least recently used past a fixed number of entries. Results of slow
+//! decoders (commands, servers and plugins) are also kept on disk under
+//! `$XDG_CACHE_HOME/linewise/gloss` (or `~/.cache/linewise/gloss`), one file
+//! per result. Entries are keyed by a SHA-256 of the preset name, the gloss
+//! config, the files it references and the input, so editing a preset's gloss
+//! or rebuilding its decoder starts afresh.
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Results the process-wide cache keeps in memory
+const MEMORY_ENTRIES: usize = 4096;
+
+/// Memory and disk cache of gloss results
+#[derive(Debug)]
+pub struct GlossCache {
+    memory: Mutex<Lru>,
+    /// Where results are persisted, if a cache directory could be found
+    dir: Option<PathBuf>,
+}
+
+/// In-memory results, evicted least recently used first
+#[derive(Debug)]
+struct Lru {
+    capacity: usize,
+    /// Value and last use of each key
+    entries: HashMap<String, (String, u64)>,
+    /// Keys by last use, oldest first
+    order: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<String> {
+        self.clock += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = self.clock;
+        self.order.insert(self.clock, key.to_string());
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: &str, value: &str) {
+        self.clock += 1;
+        let entry = (value.to_string(), self.clock);
+        if let Some((_, used)) = self.entries.insert(key.to_string(), entry) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.clock, key.to_string());
+        while self.entries.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+impl GlossCache {
+    /// A cache holding up to `capacity` results in memory
+    pub fn new(dir: Option<PathBuf>, capacity: usize) -> Self {
+        Self {
+            memory: Mutex::new(Lru::new(capacity)),
+            dir,
+        }
+    }
+
+    /// The process-wide cache, stored in the default directory
+    pub fn global() -> &'static GlossCache {
+        static CACHE: OnceLock<GlossCache> = OnceLock::new();
+        CACHE.get_or_init(|| GlossCache::new(default_dir(), MEMORY_ENTRIES))
+    }
+
+    /// Cache key for one input under a preset and gloss config
+    pub fn key(scope: &str, input: &str) -> String {
+        let mut hasher = Sha256::new();
+        // Length prefixes keep ("ab", "c") and ("a", "bc") apart
+        for part in [scope, input] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let (prefix, rest) = key.split_at(2);
+        Some(self.dir.as_ref()?.join(prefix).join(rest))
+    }
+
+    /// Look up a result in memory, then on disk if it is `persisted` there
+    pub fn get(&self, key: &str, persisted: bool) -> Option<String> {
+        if let Some(hit) = self.memory.lock().unwrap().get(key) {
+            return Some(hit);
+        }
+        if !persisted {
+            return None;
+        }
+        let value = fs::read_to_string(self.path(key)?).ok()?;
+        (self.memory.lock().unwrap()).insert(key, &value);
+        Some(value)
+    }
+
+    /// Store a result, on disk too if `persist` is set. Failing to write to
+    /// disk only loses persistence.
+    pub fn put(&self, key: &str, value: &str, persist: bool) {
+        (self.memory.lock().unwrap()).insert(key, value);
+        if let Some(path) = self.path(key).filter(|_| persist) {
+            let _ = write_atomic(&path, value);
+        }
+    }
+}
+
+/// Size and modification time of each file, so a cache key changes when one
+/// of them is replaced. Programs named without a path are looked up in `PATH`.
+pub fn file_stamps<'a>(paths: impl IntoIterator<Item = &'a Path>) -> String {
+    let mut stamps = String::new();
+    for path in paths {
+        let found = if path.components().count() > 1 {
+            Some(path.to_path_buf())
+        } else {
+            which(path)
+        };
+        let meta = found.and_then(|p| fs::metadata(p).ok());
+        let modified = (meta.as_ref())
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok());
+        match (meta, modified) {
+            (Some(meta), Some(modified)) => stamps.push_str(&format!(
+                "{} {} {}\n",
+                path.display(),
+                meta.len(),
+                modified.as_nanos()
+            )),
+            _ => stamps.push_str(&format!("{} -\n", path.display())),
+        }
+    }
+    stamps
+}
+
+/// A bare file name as found in the working directory or on `PATH`
+fn which(name: &Path) -> Option<PathBuf> {
+    if name.is_file() {
+        return Some(name.to_path_buf());
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// `$XDG_CACHE_HOME/linewise/gloss`, falling back to `~/.cache/linewise/gloss`
+pub fn default_dir() -> Option<PathBuf> {
+    if let Ok(xdg) = std::env::var("XDG_CACHE_HOME") {
+        if !xdg.is_empty() {
+            return Some(PathBuf::from(format!("{}/linewise/gloss", xdg)));
+        }
+    }
+    let home = std::env::var("HOME").ok()?;
+    Some(PathBuf::from(format!("{}/.cache/linewise/gloss", home)))
+}
+
+/// Write via a temporary file so readers never see half an entry
+fn write_atomic(path: &Path, value: &str) -> Result<()> {
+    let parent = path.parent().context("cache path has no parent")?;
+    fs::create_dir_all(parent)?;
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&tmp, value)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Number of entries and their total size in a cache directory
+fn dir_stats(dir: &Path) -> (usize, u64) {
+    let mut entries = 0;
+    let mut bytes = 0;
+    for shard in fs::read_dir(dir).into_iter().flatten().flatten() {
+        for entry in fs::read_dir(shard.path()).into_iter().flatten().flatten() {
+            if let Ok(meta) = entry.metadata() {
+                if meta.is_file() {
+                    entries += 1;
+                    bytes += meta.len();
+                }
+            }
+        }
+    }
+    (entries, bytes)
+}
+
+/// `lw cache stats`
+pub fn stats() -> Result<()> {
+    let dir = default_dir().context("no cache directory (set HOME or XDG_CACHE_HOME)")?;
+    let (entries, bytes) = dir_stats(&dir);
+    println!("Location: {}", dir.display());
+    println!("Entries:  {}", entries);
+    println!("Size:     {} bytes", bytes);
+    Ok(())
+}
+
+/// `lw cache clear`
+pub fn clear() -> Result<()> {
+    let dir = default_dir().context("no cache directory (set HOME or XDG_CACHE_HOME)")?;
+    let (entries, _) = dir_stats(&dir);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("failed to remove {:?}", dir))?;
+    }
+    println!("Removed {} cached gloss result(s)", entries);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = GlossCache::key("base64 {}", "aGk=");
+        assert_ne!(key, GlossCache::key("base64 {}", "aGl="));
+        assert_ne!(key, GlossCache::key("base64 {}a", "Gk="));
+
+        let cache = GlossCache::new(Some(dir.path().to_path_buf()), 2);
+        assert_eq!(cache.get(&key, true), None);
+        cache.put(&key, "6869", true);
+        assert_eq!(cache.get(&key, true).as_deref(), Some("6869"));
+
+        // A new run reads it back from disk
+        let again = GlossCache::new(Some(dir.path().to_path_buf()), 2);
+        assert_eq!(again.get(&key, true).as_deref(), Some("6869"));
+        assert_eq!(dir_stats(dir.path()), (1, 4));
+
+        // Results that aren't persisted live in memory only, least recently used
+        // dropped first
+        let cache = GlossCache::new(Some(dir.path().to_path_buf()), 2);
+        cache.put("a1", "1", false);
+        cache.put("b2", "2", false);
+        assert_eq!(cache.get("a1", false).as_deref(), Some("1"));
+        cache.put("c3", "3", false);
+        assert_eq!(cache.get("b2", false), None);
+        assert_eq!(cache.get("a1", false).as_deref(), Some("1"));
+        assert_eq!(cache.get("c3", false).as_deref(), Some("3"));
+        assert_eq!(dir_stats(dir.path()), (1, 4));
+    }
+
+    #[test]
+    fn test_file_stamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("decoder.wasm");
+        let missing = dir.path().join("missing.wasm");
+        fs::write(&path, b"one").unwrap();
+        let before = file_stamps([path.as_path(), missing.as_path()]);
+        assert!(before.ends_with("missing.wasm -\n"));
+
+        fs::write(&path, b"three").unwrap();
+        assert_ne!(
+            file_stamps([path.as_path()]),
+            file_stamps([missing.as_path()])
+        );
+        assert_ne!(before, file_stamps([path.as_path(), missing.as_path()]));
+        assert!(!file_stamps([Path::new("sh")]).ends_with(" -\n"));
+    }
+}
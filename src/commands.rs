@@ -1,5 +1,14 @@
-use crate::analysis::PositionStats;
+use crate::analysis::{self, PlaintextModel, PositionStats};
+use crate::detect;
+use crate::preset::{
+    gloss_bytes, CompiledGloss, CompiledPreset, GlossConfig, PresetFixtures, PresetManager,
+    PresetTest,
+};
 use crate::records::{filter_by_position, group_by_position};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub fn group_analysis(records: &[Vec<u8>], group_position: usize, max_positions: usize) {
     let groups = group_by_position(records, group_position);
@@ -145,3 +154,470 @@ pub fn compare_groups(records: &[Vec<u8>], group_position: usize, max_positions:
         println!("{:>4}  {}{}", pos, row, marker);
     }
 }
+
+/// `lw xorkey`: rank repeating-key lengths, then guess the key for the best one
+pub fn xor_key_analysis(records: &[Vec<u8>], max_len: usize, plaintext: &str) -> Result<()> {
+    let model = match plaintext {
+        "zeros" => PlaintextModel::zeros(),
+        "text" => PlaintextModel::text(),
+        path => PlaintextModel::from_sample(
+            &fs::read(path)
+                .with_context(|| format!("failed to read plaintext sample {:?}", path))?,
+        ),
+    };
+
+    let record_refs: Vec<&Vec<u8>> = records.iter().collect();
+    let max_record = records.iter().map(|r| r.len()).max().unwrap_or(0);
+    let stats: Vec<PositionStats> = (0..max_record)
+        .filter_map(|pos| PositionStats::from_records(&record_refs, pos))
+        .collect();
+
+    let scores = analysis::xor_key_lengths(&stats, max_len);
+    let Some(len) = analysis::best_xor_key_length(&scores) else {
+        println!("Not enough data to guess a key");
+        return Ok(());
+    };
+
+    println!("Records: {}", records.len());
+    // Multiples of the key length score as well as it, so list by length
+    let best = scores.iter().map(|(_, score)| *score).fold(0.0, f64::max);
+    println!("\nKey lengths by index of coincidence (random data: 0.0039):\n");
+    println!("{:>4}  {:>8}", "Len", "IC");
+    println!("{}", "-".repeat(20));
+    let likely = scores.iter().filter(|(_, score)| *score >= best / 2.0);
+    for (candidate, score) in likely.take(12) {
+        let marker = if *candidate == len {
+            "  <-- shortest likely"
+        } else {
+            ""
+        };
+        println!("{:>4}  {:>8.4}{}", candidate, score, marker);
+    }
+
+    let key = analysis::guess_xor_key(&stats, len, &model);
+    println!(
+        "\nKey ({} bytes, assuming {} plaintext): {}",
+        len,
+        plaintext,
+        hex::encode(&key)
+    );
+    println!("\nDecoded records (first 64 bytes):\n");
+    for record in records.iter().take(5) {
+        let decoded: Vec<u8> = (record.iter().take(64).enumerate())
+            .map(|(i, b)| b ^ key[i % len])
+            .collect();
+        let printable = |c: char| !c.is_control() || c.is_ascii_whitespace();
+        match std::str::from_utf8(&decoded) {
+            Ok(text) if text.chars().all(printable) => println!("  {}", text.escape_debug()),
+            _ => println!("  {}", hex::encode(&decoded)),
+        }
+    }
+    println!("\nTo gloss with this key:\n");
+    println!(
+        "[gloss]\ntransform = \"xor\"\nxor_key = \"{}\"",
+        hex::encode(&key)
+    );
+    Ok(())
+}
+
+/// Non-empty, trimmed lines of a file ("-" for stdin)
+fn read_lines(input: &Path) -> Result<Vec<String>> {
+    use std::io::{self, BufRead};
+
+    let reader: Box<dyn BufRead> = if input.to_string_lossy() == "-" {
+        Box::new(io::BufReader::new(io::stdin()))
+    } else {
+        Box::new(io::BufReader::new(
+            fs::File::open(input).with_context(|| format!("Failed to open {:?}", input))?,
+        ))
+    };
+    let mut lines = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(line.trim().to_string());
+        }
+    }
+    Ok(lines)
+}
+
+/// Rank every preset against the input lines, optionally explaining each rule
+pub fn detect(input: &Path, explain: bool) -> Result<()> {
+    let lines = read_lines(input)?;
+    let records: Vec<&[u8]> = lines.iter().map(|l| l.as_bytes()).collect();
+
+    let mut mgr = PresetManager::new();
+    mgr.load_all()?;
+    let presets: Vec<_> = mgr
+        .list()
+        .into_iter()
+        .filter_map(|name| mgr.compiled(name).map(|p| (name, p)))
+        .collect();
+
+    let candidates = detect::rank(&presets, &records);
+    let confidence = detect::confidence(&candidates);
+
+    if explain {
+        for (candidate, pct) in candidates.iter().zip(&confidence) {
+            println!(
+                "{}  {}/{} records, {:.1} bits, {:.1}%",
+                candidate.name,
+                candidate.matched,
+                candidate.total,
+                candidate.preset.specificity(),
+                pct
+            );
+            let rules = &candidate.preset.preset.detect;
+            let weights = candidate.preset.rule_weights();
+            let width = rules.iter().map(|r| r.describe().len()).max().unwrap_or(0);
+            for ((rule, hits), bits) in rules.iter().zip(&candidate.rule_hits).zip(weights) {
+                let mark = match *hits {
+                    0 => '-',
+                    n if n == candidate.total => '+',
+                    _ => '~',
+                };
+                println!(
+                    "  {} {:<width$}  {}/{}  {:.1} bits",
+                    mark,
+                    rule.describe(),
+                    hits,
+                    candidate.total,
+                    bits,
+                    width = width
+                );
+            }
+            println!();
+        }
+    } else {
+        println!(
+            "{:<20} {:>10} {:>12} {:>11}",
+            "Preset", "Matched", "Specificity", "Confidence"
+        );
+        println!("{}", "-".repeat(56));
+        for (candidate, pct) in candidates.iter().zip(&confidence) {
+            if candidate.matched == 0 {
+                continue;
+            }
+            println!(
+                "{:<20} {:>10} {:>7.1} bits {:>10.1}%",
+                candidate.name,
+                format!("{}/{}", candidate.matched, candidate.total),
+                candidate.preset.specificity(),
+                pct
+            );
+        }
+        println!();
+    }
+
+    match candidates.first().filter(|c| c.matched > 0) {
+        Some(best) => println!("Best match: {}", best.name),
+        None => println!("No preset matches"),
+    }
+    Ok(())
+}
+
+/// Encode hex lines back to records with a preset's (or an ad-hoc) transforms,
+/// or with `check`, verify that decoding then encoding each record reproduces it
+pub fn encode(
+    input: &Path,
+    preset_name: Option<&str>,
+    transform: Option<String>,
+    charset: Option<String>,
+    check: bool,
+) -> Result<()> {
+    let gloss = match (preset_name, transform) {
+        (_, Some(transform)) => Arc::new(CompiledGloss::new(GlossConfig {
+            transform: Some(transform),
+            base85_charset: charset,
+            ..Default::default()
+        })?),
+        (Some(name), None) => {
+            let mut mgr = PresetManager::new();
+            mgr.load_all()?;
+            mgr.compiled(name)
+                .ok_or_else(|| anyhow::anyhow!("unknown preset '{}'", name))?
+                .gloss
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("preset '{}' has no gloss config", name))?
+        }
+        (None, None) => anyhow::bail!("specify --preset or --transform"),
+    };
+
+    let lines = read_lines(input)?;
+
+    if !check {
+        for (i, line) in lines.iter().enumerate() {
+            let bytes = hex::decode(line.replace([' ', '\t'], ""))
+                .with_context(|| format!("line {}: expected hex", i + 1))?;
+            println!("{}", gloss.config.encode(&bytes)?);
+        }
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let encoded = gloss.segment(line);
+        let result = gloss
+            .config
+            .decode(encoded)
+            .and_then(|bytes| gloss.config.encode(&bytes));
+        match result {
+            Ok(again) if again == encoded => println!("ok    line {}", i + 1),
+            Ok(again) => {
+                failed += 1;
+                println!("FAIL  line {}: {}", i + 1, encoded);
+                println!("      re-encoded: {}", again);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL  line {}: {}", i + 1, encoded);
+                println!("      {:#}", e);
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", lines.len() - failed, failed);
+    if failed > 0 {
+        anyhow::bail!("{} of {} records did not round-trip", failed, lines.len());
+    }
+    Ok(())
+}
+
+/// Run a preset's fixture tests, failing if any expectation is not met
+pub async fn preset_test(preset_arg: &str, fixture_files: &[PathBuf]) -> Result<()> {
+    preset_test_with(PresetManager::new(), preset_arg, fixture_files).await
+}
+
+/// `preset_test` with presets found through `mgr`'s search paths, so tests
+/// can leave out the user's preset directories
+async fn preset_test_with(
+    mut mgr: PresetManager,
+    preset_arg: &str,
+    fixture_files: &[PathBuf],
+) -> Result<()> {
+    let arg_path = Path::new(preset_arg);
+    let name = if arg_path.is_file() {
+        mgr.load_all_with(&[arg_path])?;
+        mgr.name_for(arg_path)
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("failed to load preset {:?}", arg_path))?
+    } else {
+        mgr.load_all()?;
+        preset_arg.to_string()
+    };
+    let preset = mgr
+        .compiled(&name)
+        .ok_or_else(|| anyhow::anyhow!("unknown preset '{}'", name))?;
+
+    // Gather fixtures: inline [[test]], sidecar <stem>.tests.toml, then --fixture files
+    let mut suites: Vec<(String, Vec<PresetTest>)> = Vec::new();
+    if !preset.preset.tests.is_empty() {
+        suites.push((name.clone(), preset.preset.tests.clone()));
+    }
+    let sidecar = mgr.path(&name).map(|p| p.with_extension("tests.toml"));
+    for path in sidecar.iter().filter(|p| p.is_file()).chain(fixture_files) {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+        let fixtures: PresetFixtures =
+            toml::from_str(&content).with_context(|| format!("failed to parse {:?}", path))?;
+        suites.push((path.display().to_string(), fixtures.tests));
+    }
+
+    let total: usize = suites.iter().map(|(_, tests)| tests.len()).sum();
+    if total == 0 {
+        anyhow::bail!("no tests found for preset '{}'", name);
+    }
+
+    let mut failed = 0;
+    for (source, tests) in &suites {
+        for (idx, test) in tests.iter().enumerate() {
+            let problems = run_preset_test(preset, test).await;
+            if problems.is_empty() {
+                println!("ok    {}#{}", source, idx + 1);
+            } else {
+                failed += 1;
+                println!("FAIL  {}#{}: {}", source, idx + 1, test.input);
+                for line in problems {
+                    println!("      {}", line);
+                }
+            }
+        }
+    }
+
+    println!("\n{} passed, {} failed", total - failed, failed);
+    if failed > 0 {
+        anyhow::bail!("{} of {} preset tests failed", failed, total);
+    }
+    Ok(())
+}
+
+/// Check one fixture, returning a description of each mismatch
+async fn run_preset_test(preset: &CompiledPreset, test: &PresetTest) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let detected = preset.matches(test.input.as_bytes());
+    if detected != test.detect {
+        problems.push(format!(
+            "detect: expected {}, got {}",
+            if test.detect { "match" } else { "no match" },
+            if detected { "match" } else { "no match" }
+        ));
+    }
+
+    let glossed = match &preset.gloss {
+        // Each row of a `segments = "rows"` gloss on its own line
+        Some(gloss) => (gloss.apply_uncached(&test.input).await.into_iter())
+            .map(|result| result.unwrap_or_else(|e| format!("[error] {}", e)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => test.input.clone(),
+    };
+
+    if let Some(expected) = &test.gloss {
+        if expected.trim() != glossed.trim() {
+            problems.push("gloss:".to_string());
+            problems.extend(line_diff(expected.trim(), glossed.trim()));
+        }
+    }
+
+    for (field_name, expected) in &test.fields {
+        let layout = &preset.preset.layout;
+        let actual = if let Some(value) = preset.field_value(field_name, &test.input, &glossed) {
+            value
+        } else if let Some(field) = layout.iter().find(|f| &f.name == field_name) {
+            let bytes = if preset.gloss.is_some() {
+                gloss_bytes(&glossed)
+            } else {
+                test.input.as_bytes().to_vec()
+            };
+            field.decode(&bytes)
+        } else {
+            problems.push(format!("field {}: no such field in preset", field_name));
+            continue;
+        };
+
+        if &actual != expected {
+            problems.push(format!(
+                "field {}: expected {:?}, got {:?}",
+                field_name, expected, actual
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Line diff of expected vs actual output ("-" expected only, "+" actual only)
+fn line_diff(expected: &str, actual: &str) -> Vec<String> {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push(format!("  {}", a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(format!("- {}", a[i]));
+            i += 1;
+        } else {
+            out.push(format!("+ {}", b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset::Preset;
+
+    const PRESET: &str = r#"
+[preset]
+name = "escaped"
+
+[[detect]]
+type = "regex"
+pattern = "%[0-9A-F]{2}"
+
+[gloss]
+transform = "url"
+
+[[fields]]
+name = "word"
+from_gloss = true
+pattern = "(\\w+)"
+
+[[test]]
+input = "hello%20world"
+gloss = "hello world"
+fields = { word = "hello" }
+"#;
+
+    #[test]
+    fn test_line_diff() {
+        assert_eq!(line_diff("a\nb", "a\nb"), vec!["  a", "  b"]);
+        assert_eq!(
+            line_diff("a\nb\nc", "a\nx\nc"),
+            vec!["  a", "- b", "+ x", "  c"]
+        );
+        assert_eq!(line_diff("a", "a\nb\nc"), vec!["  a", "+ b", "+ c"]);
+        assert_eq!(line_diff("a\nb", ""), vec!["- a", "- b"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_preset_test() {
+        let preset: Preset = toml::from_str(PRESET).unwrap();
+        let preset = CompiledPreset::new(preset).unwrap();
+        let passing = run_preset_test(&preset, &preset.preset.tests[0]).await;
+        assert_eq!(passing, Vec::<String>::new());
+
+        let failing: PresetFixtures = toml::from_str(
+            r#"
+[[test]]
+input = "goodbye"
+gloss = "hello"
+fields = { word = "help", missing = "x" }
+"#,
+        )
+        .unwrap();
+        let problems = run_preset_test(&preset, &failing.tests[0]).await;
+        assert_eq!(problems[0], "detect: expected match, got no match");
+        assert_eq!(problems[1], "gloss:");
+        assert_eq!(problems[2], "- hello");
+        assert_eq!(problems[3], "+ goodbye");
+        assert_eq!(problems[4], "field missing: no such field in preset");
+        assert!(problems[5].starts_with("field word: expected \"help\""));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("escaped.toml");
+        fs::write(&path, PRESET).unwrap();
+        // No search paths, so only the embedded presets and this file load
+        let arg = path.to_str().unwrap();
+        assert!(preset_test_with(PresetManager::default(), arg, &[])
+            .await
+            .is_ok());
+
+        let fixture = dir.path().join("extra.toml");
+        fs::write(&fixture, "[[test]]\ninput = \"a%2Fb\"\ngloss = \"a-b\"\n").unwrap();
+        let err = preset_test_with(PresetManager::default(), arg, &[fixture])
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "1 of 2 preset tests failed");
+    }
+}
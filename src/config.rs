@@ -1,18 +1,31 @@
+use crate::preset::{match_byte_pattern, parse_byte_pattern};
 use anyhow::Result;
-use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use std::fs;
 use std::path::Path;
 
 #[derive(Debug, Clone)]
 pub struct Rule {
     pub rule_type: String,
+    /// Byte position (`byte_equals`, `bytes`), or remainder for `length_modulo`
     pub position: Option<usize>,
     pub value: Option<u8>,
+    /// Length bound (`min_length`, `max_length`, `length_equals`), or modulus for `length_modulo`
     pub length: Option<usize>,
+    /// Byte pattern for `bytes`, `None` entries match any byte
+    pub pattern: Vec<Option<u8>>,
+    /// Nested rules for `any`, `all` and `not`
+    pub rules: Vec<Rule>,
 }
 
 impl Rule {
-    /// Parse a rule from a line like "byte_equals 0 33" or "min_length 30"
+    /// Parse a rule from a line like "byte_equals 0 33" or "min_length 30".
+    ///
+    /// Byte patterns and combinators:
+    /// - `bytes 0 21 ?? 00 ff` (or `bytes * ...` to match anywhere)
+    /// - `length_equals 20`, `length_modulo 4 [remainder]`
+    /// - `not <rule>`, `any <rule> ; <rule> ...`, `all <rule> ; <rule> ...`
     pub fn from_line(line: &str) -> Option<Self> {
         let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.is_empty() {
@@ -20,41 +33,92 @@ impl Rule {
         }
 
         let rule_type = parts[0].to_string();
+        let rest = line.trim_start()[rule_type.len()..].trim();
+        let base = Rule {
+            rule_type: rule_type.clone(),
+            position: None,
+            value: None,
+            length: None,
+            pattern: Vec::new(),
+            rules: Vec::new(),
+        };
         match rule_type.as_str() {
             "byte_equals" => {
                 // byte_equals <position> <value>
                 let position = parts.get(1)?.parse().ok();
                 let value = parts.get(2)?.parse().ok();
                 Some(Rule {
-                    rule_type,
                     position,
                     value,
-                    length: None,
+                    ..base
                 })
             }
-            "min_length" => {
+            "min_length" | "max_length" | "length_equals" => {
                 // min_length <length>
                 let length = parts.get(1)?.parse().ok();
+                Some(Rule { length, ..base })
+            }
+            "length_modulo" => {
+                // length_modulo <modulus> [remainder]
+                let modulus = parts.get(1)?.parse().ok().filter(|m| *m > 0)?;
+                let remainder = parts.get(2).map_or(Some(0), |r| r.parse().ok())?;
                 Some(Rule {
-                    rule_type,
-                    position: None,
-                    value: None,
-                    length,
+                    length: Some(modulus),
+                    position: Some(remainder),
+                    ..base
                 })
             }
-            "max_length" => {
-                let length = parts.get(1)?.parse().ok();
+            "bytes" => {
+                // bytes <offset|*> <hex pattern>
+                let position = match *parts.get(1)? {
+                    "*" => None,
+                    offset => Some(offset.parse().ok()?),
+                };
+                let pattern = parse_byte_pattern(&parts[2..].join("")).ok()?;
                 Some(Rule {
-                    rule_type,
-                    position: None,
-                    value: None,
-                    length,
+                    position,
+                    pattern,
+                    ..base
                 })
             }
+            "not" => Some(Rule {
+                rules: vec![Rule::from_line(rest)?],
+                ..base
+            }),
+            "any" | "all" => {
+                let rules = rest
+                    .split(';')
+                    .map(Rule::from_line)
+                    .collect::<Option<Vec<_>>>()?;
+                Some(Rule { rules, ..base })
+            }
             _ => None,
         }
     }
 
+    /// How much a match says about the record, in bits (as `DetectRule`)
+    fn specificity(&self) -> f64 {
+        match self.rule_type.as_str() {
+            "byte_equals" => 8.0,
+            "min_length" | "max_length" => 1.0,
+            "length_equals" => 4.0,
+            "length_modulo" => (self.length.unwrap_or(1).max(1) as f64).log2(),
+            "bytes" => {
+                let per_byte = if self.position.is_some() { 8.0 } else { 4.0 };
+                per_byte * self.pattern.iter().flatten().count() as f64
+            }
+            "any" => self
+                .rules
+                .iter()
+                .map(Rule::specificity)
+                .reduce(f64::min)
+                .unwrap_or(0.0),
+            "all" => self.rules.iter().map(Rule::specificity).sum(),
+            "not" => 1.0,
+            _ => 0.0,
+        }
+    }
+
     pub fn matches(&self, record: &[u8]) -> bool {
         match self.rule_type.as_str() {
             "byte_equals" => {
@@ -70,15 +134,66 @@ impl Rule {
                 let len = self.length.unwrap_or(usize::MAX);
                 record.len() <= len
             }
+            "length_equals" => self.length == Some(record.len()),
+            "length_modulo" => {
+                let modulus = self.length.unwrap_or(1).max(1);
+                record.len() % modulus == self.position.unwrap_or(0)
+            }
+            "bytes" => {
+                !self.pattern.is_empty() && match_byte_pattern(record, &self.pattern, self.position)
+            }
+            "any" => self.rules.iter().any(|rule| rule.matches(record)),
+            "all" => self.rules.iter().all(|rule| rule.matches(record)),
+            "not" => !self.rules.iter().all(|rule| rule.matches(record)),
             _ => false,
         }
     }
 }
 
+/// Records sampled per preset when no `sample_size` is set
+pub const DEFAULT_SAMPLE_SIZE: usize = 50;
+/// Percentage of sampled records that must match when no `threshold` is set
+pub const DEFAULT_THRESHOLD: usize = 80;
+
 #[derive(Debug, Clone)]
 pub struct PresetRules {
     pub name: String,
     pub rules: Vec<Rule>,
+    /// Records to sample for detection (`sample_size N` in `@rules`)
+    pub sample_size: usize,
+    /// Percentage of samples that must match (`threshold N` in `@rules`)
+    pub threshold: usize,
+}
+
+/// How records are picked for preset detection
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Sampling {
+    /// Evenly spaced records from first to last; the same file always gives the same sample
+    #[default]
+    Stratified,
+    /// Random records from a seeded generator, reproducible with the same seed
+    Seeded(u64),
+}
+
+impl Sampling {
+    /// Pick up to `size` records, in file order
+    pub fn sample<'a>(&self, records: &'a [Vec<u8>], size: usize) -> Vec<&'a Vec<u8>> {
+        if records.len() <= size {
+            return records.iter().collect();
+        }
+        let mut indices: Vec<usize> = match self {
+            Sampling::Stratified if size == 1 => vec![records.len() / 2],
+            Sampling::Stratified => (0..size)
+                .map(|i| i * (records.len() - 1) / (size - 1))
+                .collect(),
+            Sampling::Seeded(seed) => {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                rand::seq::index::sample(&mut rng, records.len(), size).into_vec()
+            }
+        };
+        indices.sort_unstable();
+        indices.into_iter().map(|i| &records[i]).collect()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -107,6 +222,9 @@ impl Config {
             }
         }
 
+        // Directory order is arbitrary; keep detection ties stable
+        config.presets.sort_by(|a, b| a.name.cmp(&b.name));
+
         Ok(config)
     }
 
@@ -115,6 +233,8 @@ impl Config {
         let name = path.file_stem()?.to_string_lossy().to_string();
 
         let mut rules = Vec::new();
+        let mut sample_size = DEFAULT_SAMPLE_SIZE;
+        let mut threshold = DEFAULT_THRESHOLD;
         let mut in_rules_section = false;
 
         for line in content.lines() {
@@ -131,51 +251,103 @@ impl Config {
             }
 
             if in_rules_section && !line.starts_with('#') {
-                if let Some(rule) = Rule::from_line(line) {
-                    rules.push(rule);
+                // Detection settings share the section with the rules
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next().and_then(|v| v.parse().ok())) {
+                    (Some("sample_size"), Some(n)) if n > 0 => sample_size = n,
+                    (Some("threshold"), Some(pct)) if pct <= 100 => threshold = pct,
+                    _ => {
+                        if let Some(rule) = Rule::from_line(line) {
+                            rules.push(rule);
+                        }
+                    }
                 }
             }
         }
 
-        Some(PresetRules { name, rules })
+        Some(PresetRules {
+            name,
+            rules,
+            sample_size,
+            threshold,
+        })
     }
 
-    pub fn detect_preset(&self, records: &[Vec<u8>], sample_size: usize) -> Option<String> {
+    /// Pick the preset whose rules match the most sampled records, if any
+    /// reaches its threshold. Each preset is checked against its own sample size.
+    pub fn detect_preset(&self, records: &[Vec<u8>], sampling: Sampling) -> Option<String> {
         if records.is_empty() || self.presets.is_empty() {
             return None;
         }
 
-        let mut rng = rand::thread_rng();
-        let samples: Vec<&Vec<u8>> = if records.len() <= sample_size {
-            records.iter().collect()
-        } else {
-            records.choose_multiple(&mut rng, sample_size).collect()
-        };
-
-        let mut best_match: Option<(String, usize)> = None;
+        // Rank by match rate, then specificity; presets are sorted by name,
+        // so the first of equals wins
+        let mut best_match: Option<(&str, f64, f64)> = None;
 
         for preset in &self.presets {
             if preset.rules.is_empty() {
                 continue;
             }
 
+            let samples = sampling.sample(records, preset.sample_size);
             let matches = samples
                 .iter()
                 .filter(|record| preset.rules.iter().all(|rule| rule.matches(record)))
                 .count();
+            let rate = matches as f64 / samples.len() as f64;
+            let specificity: f64 = preset.rules.iter().map(Rule::specificity).sum();
 
-            let threshold = (samples.len() * 80) / 100;
-            if matches >= threshold {
-                match &best_match {
-                    None => best_match = Some((preset.name.clone(), matches)),
-                    Some((_, best_count)) if matches > *best_count => {
-                        best_match = Some((preset.name.clone(), matches));
+            if matches * 100 >= samples.len() * preset.threshold {
+                let better = match best_match {
+                    None => true,
+                    Some((_, best_rate, best_spec)) => {
+                        (rate, specificity).partial_cmp(&(best_rate, best_spec))
+                            == Some(std::cmp::Ordering::Greater)
                     }
-                    _ => {}
+                };
+                if better {
+                    best_match = Some((&preset.name, rate, specificity));
                 }
             }
         }
 
-        best_match.map(|(name, _)| name)
+        best_match.map(|(name, _, _)| name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_combinators() {
+        let rule = Rule::from_line("any bytes 0 21 ?? 00 ; bytes * ca fe").unwrap();
+        assert!(rule.matches(&[0x21, 0x99, 0x00]));
+        assert!(rule.matches(&[0x01, 0xca, 0xfe]));
+        assert!(!rule.matches(&[0x22, 0x99, 0x00]));
+
+        let rule = Rule::from_line("not length_modulo 4 1").unwrap();
+        assert!(rule.matches(&[0; 4]));
+        assert!(!rule.matches(&[0; 5]));
+
+        assert!(Rule::from_line("length_modulo 0").is_none());
+        assert!(Rule::from_line("any min_length 3 ; bogus").is_none());
+    }
+
+    #[test]
+    fn test_sampling_is_reproducible() {
+        let records: Vec<Vec<u8>> = (0..100u8).map(|i| vec![i]).collect();
+
+        let stratified = Sampling::Stratified.sample(&records, 5);
+        let firsts: Vec<u8> = stratified.iter().map(|r| r[0]).collect();
+        assert_eq!(firsts, vec![0, 24, 49, 74, 99]);
+
+        let a = Sampling::Seeded(7).sample(&records, 10);
+        let b = Sampling::Seeded(7).sample(&records, 10);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 10);
+        assert!(a.windows(2).all(|w| w[0][0] < w[1][0]));
+
+        assert_eq!(Sampling::Stratified.sample(&records[..3], 5).len(), 3);
     }
 }
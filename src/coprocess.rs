@@ -0,0 +1,199 @@
+//! Persistent gloss commands
+//!
+//! With `server` set, a gloss command is started once and records are streamed
+//! to it over stdin, reading one response per record from stdout. A helper that
+//! exits, hangs past the timeout or breaks the framing is killed and started
+//! again.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+/// How records and responses are delimited on the helper's stdin/stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Framing {
+    /// One record per line in, one line of output back
+    Lines,
+    /// `{"record": "..."}` per line in, `{"output": ...}` or `{"error": "..."}` back
+    Json,
+}
+
+/// A running helper process
+#[derive(Debug)]
+struct Process {
+    // Held so the process is killed when dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Process {
+    fn spawn(cmd: &[String]) -> Result<Self> {
+        let mut child = Command::new(&cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to start {}", cmd[0]))?;
+        let stdin = child.stdin.take().context("helper stdin unavailable")?;
+        let stdout = child.stdout.take().context("helper stdout unavailable")?;
+        Ok(Self {
+            _child: child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    /// Send one record and read its response. The outer error means the helper
+    /// can't be used any more; the inner one is an error it reported for the record.
+    async fn request(&mut self, framing: Framing, record: &str) -> Result<Result<String, String>> {
+        let mut frame = match framing {
+            Framing::Lines if record.contains('\n') => {
+                return Ok(Err(
+                    "record contains a newline (use server = \"json\")".to_string()
+                ));
+            }
+            Framing::Lines => record.to_string(),
+            Framing::Json => serde_json::json!({ "record": record }).to_string(),
+        };
+        frame.push('\n');
+        self.stdin.write_all(frame.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line).await? == 0 {
+            anyhow::bail!("helper exited");
+        }
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        match framing {
+            Framing::Lines => Ok(Ok(line.to_string())),
+            Framing::Json => {
+                let response: serde_json::Value =
+                    serde_json::from_str(line).context("helper sent invalid JSON")?;
+                if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+                    let message = error
+                        .as_str()
+                        .map_or_else(|| error.to_string(), String::from);
+                    return Ok(Err(message));
+                }
+                match response.get("output") {
+                    Some(serde_json::Value::String(output)) => Ok(Ok(output.clone())),
+                    Some(output) => Ok(Ok(serde_json::to_string_pretty(output)?)),
+                    None => anyhow::bail!("helper response has no \"output\""),
+                }
+            }
+        }
+    }
+}
+
+/// A gloss command kept running between records, restarted when it fails
+#[derive(Debug)]
+pub struct CoProcess {
+    cmd: Vec<String>,
+    framing: Framing,
+    timeout: Option<Duration>,
+    process: Mutex<Option<Process>>,
+}
+
+impl CoProcess {
+    /// The helper is started on the first record
+    pub fn new(cmd: Vec<String>, framing: Framing, timeout: Option<Duration>) -> Self {
+        Self {
+            cmd,
+            framing,
+            timeout,
+            process: Mutex::new(None),
+        }
+    }
+
+    /// Run one record through the helper, starting or restarting it as needed
+    pub async fn apply(&self, record: &str) -> Result<String> {
+        let name = &self.cmd[0];
+        let mut process = self.process.lock().await;
+
+        // A helper that died since the last record gets one restart and retry
+        let mut restarted = false;
+        loop {
+            if process.is_none() {
+                *process = Some(Process::spawn(&self.cmd)?);
+            }
+            let running = process.as_mut().expect("helper was just started");
+
+            let result = match self.timeout {
+                Some(limit) => {
+                    match tokio::time::timeout(limit, running.request(self.framing, record)).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            // Its reply would arrive as the next record's, so start over
+                            *process = None;
+                            anyhow::bail!("{} timed out after {} ms", name, limit.as_millis());
+                        }
+                    }
+                }
+                None => running.request(self.framing, record).await,
+            };
+
+            match result {
+                Ok(Ok(output)) => return Ok(output),
+                Ok(Err(message)) => anyhow::bail!("{}: {}", name, message),
+                Err(e) => {
+                    *process = None;
+                    if restarted {
+                        return Err(e.context(format!("{} failed after restarting", name)));
+                    }
+                    eprintln!("warning: restarting {}: {:#}", name, e);
+                    restarted = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["sh".into(), "-c".into(), script.into()]
+    }
+
+    #[tokio::test]
+    async fn test_coprocess() {
+        let echo = CoProcess::new(vec!["cat".into()], Framing::Lines, None);
+        assert_eq!(echo.apply("a").await.unwrap(), "a");
+        assert_eq!(echo.apply("b").await.unwrap(), "b");
+        assert!(echo.apply("two\nlines").await.is_err());
+
+        // Exits after every record, so each later one needs a restart
+        let once = CoProcess::new(sh("read line; echo \"$line!\""), Framing::Lines, None);
+        assert_eq!(once.apply("a").await.unwrap(), "a!");
+        assert_eq!(once.apply("b").await.unwrap(), "b!");
+
+        let json = CoProcess::new(
+            sh(
+                r#"while read l; do case "$l" in *bad*) echo '{"error":"nope"}';; *) echo '{"output":"ok"}';; esac; done"#,
+            ),
+            Framing::Json,
+            None,
+        );
+        assert_eq!(json.apply("good").await.unwrap(), "ok");
+        assert_eq!(json.apply("bad").await.unwrap_err().to_string(), "sh: nope");
+        assert_eq!(json.apply("good").await.unwrap(), "ok");
+
+        let slow = CoProcess::new(
+            vec!["sleep".into(), "10".into()],
+            Framing::Lines,
+            Some(Duration::from_millis(50)),
+        );
+        let err = slow.apply("a").await.unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+}
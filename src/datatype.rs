@@ -0,0 +1,688 @@
+//! Field data types shared by the interactive view and preset layouts
+//!
+//! A data type knows how to decode a value from the start of a byte slice and
+//! how many bytes that value occupies. Types are named the way they appear in
+//! `.lwpreset` lock files and `[[layout]]` preset sections:
+//!
+//! - Integers: `u8`, `u16le`, `u16be`, `u32le`, `u32be`
+//! - Varints: `varint`, `sleb128`, `zigzag`, `vlq`, `gitvarint`, `prefixvarint`
+//! - Single bytes: `hex`, `binary`, `ascii`
+//! - Timestamps: `unix32`, `unix64`, `unixms`, `filetime`, `dostime`, `gps`
+//! - Strings: `<encoding>:<termination>` where encoding is `ascii`, `utf8`,
+//!   `utf16le` or `utf16be` and termination is `fixed`, `nul`, `u8len`,
+//!   `u16len`, `u32len` or `varlen` (e.g. `utf16le:nul`, `utf8:u8len`)
+//! - Blobs: `bytes:<termination>`, rendered as hex
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    U8,
+    U16Le,
+    U16Be,
+    U32Le,
+    U32Be,
+    /// Unsigned LEB128 (protobuf `uint64`)
+    VarInt,
+    /// Signed LEB128 (two's complement, sign-extended)
+    SLeb128,
+    /// Zigzag-encoded LEB128 (protobuf `sint64`)
+    ZigZag,
+    /// MSB-first base-128 (MIDI variable-length quantity)
+    VarIntBe,
+    /// MSB-first base-128 with per-byte offset (git pack offsets)
+    GitVarInt,
+    /// Length in leading one-bits of the first byte, big-endian payload
+    PrefixVarInt,
+    Hex,
+    Binary,
+    Ascii,
+    /// u32le seconds since 1970-01-01
+    Unix32,
+    /// i64le seconds since 1970-01-01
+    Unix64,
+    /// i64le milliseconds since 1970-01-01
+    UnixMillis,
+    /// u64le 100ns intervals since 1601-01-01 (Windows FILETIME)
+    FileTime,
+    /// u32le MS-DOS time (low word) and date (high word), 2-second resolution
+    DosDateTime,
+    /// u32le seconds since 1980-01-06 (GPS epoch, no leap-second correction)
+    GpsTime,
+    /// Whole string in the given encoding, delimited by `Termination`
+    Str(StrEncoding, Termination),
+    /// Opaque byte run delimited by `Termination`, rendered as hex
+    Bytes(Termination),
+}
+
+/// Character encoding for string fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrEncoding {
+    Ascii,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// How the extent of a string or blob field is determined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// Extent comes from the locked/declared field length. Strings stop at the
+    /// first NUL so padded fixed-width names render cleanly.
+    Fixed,
+    /// Terminated by a NUL code unit (included in the width)
+    Nul,
+    /// u8 count of code units precedes the data
+    Len8,
+    /// u16le count of code units precedes the data
+    Len16,
+    /// u32le count of code units precedes the data
+    Len32,
+    /// LEB128 count of code units precedes the data (.NET `BinaryWriter` style)
+    VarLen,
+}
+
+impl StrEncoding {
+    fn name(&self) -> &'static str {
+        match self {
+            StrEncoding::Ascii => "ascii",
+            StrEncoding::Utf8 => "utf8",
+            StrEncoding::Utf16Le => "utf16le",
+            StrEncoding::Utf16Be => "utf16be",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(StrEncoding::Ascii),
+            "utf8" => Some(StrEncoding::Utf8),
+            "utf16le" | "utf16" => Some(StrEncoding::Utf16Le),
+            "utf16be" => Some(StrEncoding::Utf16Be),
+            _ => None,
+        }
+    }
+
+    /// Bytes per code unit
+    fn unit(&self) -> usize {
+        match self {
+            StrEncoding::Ascii | StrEncoding::Utf8 => 1,
+            StrEncoding::Utf16Le | StrEncoding::Utf16Be => 2,
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> String {
+        match self {
+            StrEncoding::Ascii => data
+                .iter()
+                .map(|&v| {
+                    if v.is_ascii_graphic() || v == b' ' {
+                        (v as char).to_string()
+                    } else {
+                        format!("\\x{:02x}", v)
+                    }
+                })
+                .collect(),
+            StrEncoding::Utf8 => String::from_utf8_lossy(data).into_owned(),
+            StrEncoding::Utf16Le | StrEncoding::Utf16Be => {
+                let units = data.chunks_exact(2).map(|c| {
+                    if *self == StrEncoding::Utf16Le {
+                        u16::from_le_bytes([c[0], c[1]])
+                    } else {
+                        u16::from_be_bytes([c[0], c[1]])
+                    }
+                });
+                char::decode_utf16(units)
+                    .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Termination {
+    fn name(&self) -> &'static str {
+        match self {
+            Termination::Fixed => "fixed",
+            Termination::Nul => "nul",
+            Termination::Len8 => "u8len",
+            Termination::Len16 => "u16len",
+            Termination::Len32 => "u32len",
+            Termination::VarLen => "varlen",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fixed" => Some(Termination::Fixed),
+            "nul" | "cstr" | "z" => Some(Termination::Nul),
+            "u8len" => Some(Termination::Len8),
+            "u16len" => Some(Termination::Len16),
+            "u32len" => Some(Termination::Len32),
+            "varlen" | "leb128len" => Some(Termination::VarLen),
+            _ => None,
+        }
+    }
+
+    /// Locate the payload: returns (payload start, payload length, total width).
+    /// `unit` is the code unit size used for NUL scanning and length prefixes.
+    fn extent(&self, data: &[u8], unit: usize) -> Option<(usize, usize, usize)> {
+        let (start, count) = match self {
+            Termination::Fixed => {
+                let len = data.len() - data.len() % unit;
+                let end = data[..len]
+                    .chunks_exact(unit)
+                    .position(|c| c.iter().all(|&b| b == 0))
+                    .map(|i| i * unit)
+                    .unwrap_or(len);
+                return Some((0, end, len));
+            }
+            Termination::Nul => {
+                let end = data
+                    .chunks_exact(unit)
+                    .position(|c| c.iter().all(|&b| b == 0))?
+                    * unit;
+                return Some((0, end, end + unit));
+            }
+            Termination::Len8 => (1, *data.first()? as usize),
+            Termination::Len16 => (
+                2,
+                u16::from_le_bytes([*data.first()?, *data.get(1)?]) as usize,
+            ),
+            Termination::Len32 => {
+                let b = data.get(..4)?;
+                (4, u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            }
+            Termination::VarLen => {
+                let (count, len) = varint::uleb128(data)?;
+                (len, usize::try_from(count).ok()?)
+            }
+        };
+        let len = count.checked_mul(unit)?;
+        if data.len() < start.checked_add(len)? {
+            return None;
+        }
+        Some((start, len, start + len))
+    }
+}
+
+impl DataType {
+    pub fn all() -> &'static [DataType] {
+        &[
+            DataType::U8,
+            DataType::U16Le,
+            DataType::U16Be,
+            DataType::U32Le,
+            DataType::U32Be,
+            DataType::VarInt,
+            DataType::SLeb128,
+            DataType::ZigZag,
+            DataType::VarIntBe,
+            DataType::GitVarInt,
+            DataType::PrefixVarInt,
+            DataType::Hex,
+            DataType::Binary,
+            DataType::Ascii,
+            DataType::Unix32,
+            DataType::Unix64,
+            DataType::UnixMillis,
+            DataType::FileTime,
+            DataType::DosDateTime,
+            DataType::GpsTime,
+            DataType::Str(StrEncoding::Utf8, Termination::Fixed),
+            DataType::Str(StrEncoding::Utf8, Termination::Nul),
+            DataType::Str(StrEncoding::Utf8, Termination::Len8),
+            DataType::Str(StrEncoding::Utf16Le, Termination::Fixed),
+            DataType::Str(StrEncoding::Utf16Le, Termination::Nul),
+            DataType::Bytes(Termination::Len8),
+        ]
+    }
+
+    pub fn name(&self) -> String {
+        let name = match self {
+            DataType::U8 => "u8",
+            DataType::U16Le => "u16le",
+            DataType::U16Be => "u16be",
+            DataType::U32Le => "u32le",
+            DataType::U32Be => "u32be",
+            DataType::VarInt => "varint",
+            DataType::SLeb128 => "sleb128",
+            DataType::ZigZag => "zigzag",
+            DataType::VarIntBe => "vlq",
+            DataType::GitVarInt => "gitvarint",
+            DataType::PrefixVarInt => "prefixvarint",
+            DataType::Hex => "hex",
+            DataType::Binary => "binary",
+            DataType::Ascii => "ascii",
+            DataType::Unix32 => "unix32",
+            DataType::Unix64 => "unix64",
+            DataType::UnixMillis => "unixms",
+            DataType::FileTime => "filetime",
+            DataType::DosDateTime => "dostime",
+            DataType::GpsTime => "gps",
+            DataType::Str(enc, term) => return format!("{}:{}", enc.name(), term.name()),
+            DataType::Bytes(term) => return format!("bytes:{}", term.name()),
+        };
+        name.to_string()
+    }
+
+    pub fn byte_size(&self) -> Option<usize> {
+        match self {
+            DataType::U8 | DataType::Hex | DataType::Binary | DataType::Ascii => Some(1),
+            DataType::U16Le | DataType::U16Be => Some(2),
+            DataType::U32Le
+            | DataType::U32Be
+            | DataType::Unix32
+            | DataType::DosDateTime
+            | DataType::GpsTime => Some(4),
+            DataType::Unix64 | DataType::UnixMillis | DataType::FileTime => Some(8),
+            // Fixed strings step by code unit; the lock decides the extent
+            DataType::Str(enc, Termination::Fixed) => Some(enc.unit()),
+            DataType::Bytes(Termination::Fixed) => Some(1),
+            DataType::VarInt
+            | DataType::SLeb128
+            | DataType::ZigZag
+            | DataType::VarIntBe
+            | DataType::GitVarInt
+            | DataType::PrefixVarInt
+            | DataType::Str(..)
+            | DataType::Bytes(_) => None, // Variable
+        }
+    }
+
+    /// Number of bytes this type occupies at the start of `data`.
+    /// For variable-length types this decodes the value to find its end.
+    pub fn width_at(&self, data: &[u8]) -> Option<usize> {
+        match self.byte_size() {
+            Some(size) => (data.len() >= size).then_some(size),
+            None => self.decode_sized(data).map(|(_, len)| len),
+        }
+    }
+
+    pub fn next(&self) -> DataType {
+        let all = Self::all();
+        let idx = all.iter().position(|t| t == self).unwrap_or(0);
+        all[(idx + 1) % all.len()]
+    }
+
+    pub fn prev(&self) -> DataType {
+        let all = Self::all();
+        let idx = all.iter().position(|t| t == self).unwrap_or(0);
+        all[(idx + all.len() - 1) % all.len()]
+    }
+
+    pub fn from_name(name: &str) -> Option<DataType> {
+        if let Some((kind, term)) = name.split_once(':') {
+            let term = Termination::from_name(term)?;
+            return match kind {
+                "bytes" | "blob" => Some(DataType::Bytes(term)),
+                _ => StrEncoding::from_name(kind).map(|enc| DataType::Str(enc, term)),
+            };
+        }
+        match name {
+            "u8" => Some(DataType::U8),
+            "u16le" => Some(DataType::U16Le),
+            "u16be" => Some(DataType::U16Be),
+            "u32le" => Some(DataType::U32Le),
+            "u32be" => Some(DataType::U32Be),
+            "varint" | "uleb128" | "leb128" => Some(DataType::VarInt),
+            "sleb128" => Some(DataType::SLeb128),
+            "zigzag" | "sint" => Some(DataType::ZigZag),
+            "vlq" | "varint_be" | "midi" => Some(DataType::VarIntBe),
+            "gitvarint" | "git" => Some(DataType::GitVarInt),
+            "prefixvarint" | "prefix" => Some(DataType::PrefixVarInt),
+            "hex" => Some(DataType::Hex),
+            "binary" => Some(DataType::Binary),
+            "ascii" => Some(DataType::Ascii),
+            "unix32" | "time_t" => Some(DataType::Unix32),
+            "unix64" => Some(DataType::Unix64),
+            "unixms" | "unix_millis" => Some(DataType::UnixMillis),
+            "filetime" => Some(DataType::FileTime),
+            "dostime" | "dosdatetime" => Some(DataType::DosDateTime),
+            "gps" | "gpstime" => Some(DataType::GpsTime),
+            "cstr" => Some(DataType::Str(StrEncoding::Utf8, Termination::Nul)),
+            "wstr" => Some(DataType::Str(StrEncoding::Utf16Le, Termination::Nul)),
+            _ => None,
+        }
+    }
+
+    pub fn decode(&self, data: &[u8]) -> String {
+        self.decode_sized(data)
+            .map(|(value, _)| value)
+            .unwrap_or_default()
+    }
+
+    /// Decode a value, returning its display string and the number of bytes consumed
+    pub fn decode_sized(&self, data: &[u8]) -> Option<(String, usize)> {
+        match self {
+            DataType::U8 => data.first().map(|&v| (format!("{}", v), 1)),
+            DataType::Hex => data.first().map(|&v| (format!("{:02x}", v), 1)),
+            DataType::Binary => data.first().map(|&v| (format!("{:08b}", v), 1)),
+            DataType::Ascii => data.first().map(|&v| (StrEncoding::Ascii.decode(&[v]), 1)),
+            DataType::U16Le if data.len() >= 2 => {
+                Some((format!("{}", u16::from_le_bytes([data[0], data[1]])), 2))
+            }
+            DataType::U16Be if data.len() >= 2 => {
+                Some((format!("{}", u16::from_be_bytes([data[0], data[1]])), 2))
+            }
+            DataType::U32Le if data.len() >= 4 => Some((
+                format!(
+                    "{}",
+                    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+                ),
+                4,
+            )),
+            DataType::U32Be if data.len() >= 4 => Some((
+                format!(
+                    "{}",
+                    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+                ),
+                4,
+            )),
+            DataType::VarInt => varint::uleb128(data).map(|(v, n)| (format!("{}", v), n)),
+            DataType::SLeb128 => varint::sleb128(data).map(|(v, n)| (format!("{}", v), n)),
+            DataType::ZigZag => {
+                varint::uleb128(data).map(|(v, n)| (format!("{}", varint::unzigzag(v)), n))
+            }
+            DataType::VarIntBe => varint::vlq(data).map(|(v, n)| (format!("{}", v), n)),
+            DataType::GitVarInt => varint::git(data).map(|(v, n)| (format!("{}", v), n)),
+            DataType::PrefixVarInt => varint::prefix(data).map(|(v, n)| (format!("{}", v), n)),
+            DataType::Unix32
+            | DataType::Unix64
+            | DataType::UnixMillis
+            | DataType::FileTime
+            | DataType::DosDateTime
+            | DataType::GpsTime => {
+                let (secs, nanos) = self.timestamp(data)?;
+                Some((timestamp::iso8601(secs, nanos), self.byte_size()?))
+            }
+            DataType::Str(enc, term) => {
+                let (start, len, width) = term.extent(data, enc.unit())?;
+                let text = enc.decode(&data[start..start + len]);
+                Some((format!("{:?}", text), width))
+            }
+            DataType::Bytes(term) => {
+                let (start, len, width) = term.extent(data, 1)?;
+                Some((hex::encode(&data[start..start + len]), width))
+            }
+            _ => None,
+        }
+    }
+
+    /// Seconds and nanoseconds since the Unix epoch for timestamp types
+    pub fn timestamp(&self, data: &[u8]) -> Option<(i64, u32)> {
+        let u32le = |d: &[u8]| Some(u32::from_le_bytes(d.get(..4)?.try_into().ok()?));
+        let u64le = |d: &[u8]| Some(u64::from_le_bytes(d.get(..8)?.try_into().ok()?));
+        match self {
+            DataType::Unix32 => Some((u32le(data)? as i64, 0)),
+            DataType::Unix64 => Some((u64le(data)? as i64, 0)),
+            DataType::UnixMillis => {
+                let ms = u64le(data)? as i64;
+                Some((ms.div_euclid(1000), ms.rem_euclid(1000) as u32 * 1_000_000))
+            }
+            DataType::FileTime => {
+                let ticks = u64le(data)?;
+                let secs = (ticks / 10_000_000) as i64 - timestamp::FILETIME_EPOCH_OFFSET;
+                Some((secs, (ticks % 10_000_000) as u32 * 100))
+            }
+            DataType::DosDateTime => timestamp::dos(u32le(data)?).map(|secs| (secs, 0)),
+            DataType::GpsTime => Some((u32le(data)? as i64 + timestamp::GPS_EPOCH, 0)),
+            _ => None,
+        }
+    }
+
+    pub fn display_width(&self) -> usize {
+        match self {
+            DataType::U8 => 4,                       // "255 "
+            DataType::Hex => 3,                      // "ff "
+            DataType::Binary => 9,                   // "00000000 "
+            DataType::U16Le | DataType::U16Be => 6,  // "65535 "
+            DataType::U32Le | DataType::U32Be => 11, // "4294967295 "
+            DataType::VarInt
+            | DataType::VarIntBe
+            | DataType::GitVarInt
+            | DataType::PrefixVarInt => 11,
+            DataType::SLeb128 | DataType::ZigZag => 12, // sign
+            DataType::Ascii => 2,                       // "X "
+            DataType::Unix32
+            | DataType::Unix64
+            | DataType::FileTime
+            | DataType::DosDateTime
+            | DataType::GpsTime => 21, // "2024-01-01T00:00:00Z "
+            DataType::UnixMillis => 25,                 // "2024-01-01T00:00:00.000Z "
+            DataType::Str(_, Termination::Fixed) | DataType::Bytes(Termination::Fixed) => 5,
+            DataType::Str(..) | DataType::Bytes(_) => 17,
+        }
+    }
+}
+
+/// Timestamp conversion to ISO-8601 (UTC), without a date library
+pub mod timestamp {
+    /// Seconds between 1601-01-01 and 1970-01-01
+    pub const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+    /// 1980-01-06 in Unix seconds
+    pub const GPS_EPOCH: i64 = 315_964_800;
+
+    /// Days since 1970-01-01 for a proleptic Gregorian date
+    fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+        let y = if month <= 2 { year - 1 } else { year };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let mp = (month as i64 + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146_097 + doe - 719_468
+    }
+
+    /// Proleptic Gregorian date for days since 1970-01-01
+    fn civil_from_days(days: i64) -> (i64, u32, u32) {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        (year, month, day)
+    }
+
+    pub fn iso8601(secs: i64, nanos: u32) -> String {
+        let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+        let tod = secs.rem_euclid(86_400);
+        let time = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            tod / 3600,
+            tod % 3600 / 60,
+            tod % 60
+        );
+        if nanos == 0 {
+            format!("{}Z", time)
+        } else {
+            format!("{}.{:03}Z", time, nanos / 1_000_000)
+        }
+    }
+
+    /// MS-DOS packed date/time; `None` for out-of-range fields
+    pub fn dos(value: u32) -> Option<i64> {
+        let time = value & 0xFFFF;
+        let date = value >> 16;
+        let (year, month, day) = (1980 + (date >> 9) as i64, (date >> 5) & 0xF, date & 0x1F);
+        let (hour, min, sec) = (time >> 11, (time >> 5) & 0x3F, (time & 0x1F) * 2);
+        if !(1..=12).contains(&month) || day == 0 || hour > 23 || min > 59 || sec > 59 {
+            return None;
+        }
+        let days = days_from_civil(year, month, day);
+        Some(days * 86_400 + (hour * 3600 + min * 60 + sec) as i64)
+    }
+}
+
+/// Variable-length integer decoders. Each returns the value and the number
+/// of bytes consumed, or `None` if the input ends before the terminator.
+mod varint {
+    /// Longest encoding accepted for a 64-bit value in base-128 formats
+    const MAX_BYTES: usize = 10;
+
+    /// Unsigned LEB128: little-endian 7-bit groups, high bit = continuation
+    pub fn uleb128(data: &[u8]) -> Option<(u64, usize)> {
+        let mut value: u64 = 0;
+        for (i, &byte) in data.iter().take(MAX_BYTES).enumerate() {
+            // Reject groups with bits beyond the 64th
+            let group = (byte & 0x7F) as u64;
+            let shifted = group.checked_shl(7 * i as u32)?;
+            if shifted >> (7 * i) != group {
+                return None;
+            }
+            value |= shifted;
+            if byte & 0x80 == 0 {
+                return Some((value, i + 1));
+            }
+        }
+        None
+    }
+
+    /// Signed LEB128: as uleb128, sign-extended from the last group's bit 6
+    pub fn sleb128(data: &[u8]) -> Option<(i64, usize)> {
+        let (value, len) = uleb128(data)?;
+        let bits = 7 * len as u32;
+        let last = data[len - 1];
+        if bits < 64 && last & 0x40 != 0 {
+            Some(((value | (!0u64 << bits)) as i64, len))
+        } else {
+            Some((value as i64, len))
+        }
+    }
+
+    /// Zigzag mapping used by protobuf `sint` types: 0, -1, 1, -2, ...
+    pub fn unzigzag(value: u64) -> i64 {
+        ((value >> 1) as i64) ^ -((value & 1) as i64)
+    }
+
+    /// MSB-first base-128 (MIDI VLQ): big-endian 7-bit groups
+    pub fn vlq(data: &[u8]) -> Option<(u64, usize)> {
+        let mut value: u64 = 0;
+        for (i, &byte) in data.iter().take(MAX_BYTES).enumerate() {
+            // Shifting by 7 is multiplying by 128; reject groups that overflow
+            value = value.checked_mul(1 << 7)? | (byte & 0x7F) as u64;
+            if byte & 0x80 == 0 {
+                return Some((value, i + 1));
+            }
+        }
+        None
+    }
+
+    /// Git offset encoding: MSB-first base-128, adding one per continuation
+    /// so that every value has exactly one representation
+    pub fn git(data: &[u8]) -> Option<(u64, usize)> {
+        let first = *data.first()?;
+        let mut value = (first & 0x7F) as u64;
+        if first & 0x80 == 0 {
+            return Some((value, 1));
+        }
+        for (i, &byte) in data.iter().enumerate().skip(1).take(MAX_BYTES - 1) {
+            value = value.checked_add(1)?.checked_mul(1 << 7)? | (byte & 0x7F) as u64;
+            if byte & 0x80 == 0 {
+                return Some((value, i + 1));
+            }
+        }
+        None
+    }
+
+    /// Prefix varint: the count of leading one-bits in the first byte is the
+    /// number of extra bytes; the remaining bits and extra bytes are big-endian
+    pub fn prefix(data: &[u8]) -> Option<(u64, usize)> {
+        let first = *data.first()?;
+        let extra = first.leading_ones() as usize;
+        let len = extra + 1;
+        if data.len() < len {
+            return None;
+        }
+        let mut value = if extra >= 7 {
+            0
+        } else {
+            (first & (0x7F >> extra)) as u64
+        };
+        for &byte in &data[1..len] {
+            value = (value << 8) | byte as u64;
+        }
+        Some((value, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_decoders() {
+        assert_eq!(varint::uleb128(&[0xE5, 0x8E, 0x26]), Some((624485, 3)));
+        assert_eq!(varint::uleb128(&[0x80, 0x80]), None);
+        assert_eq!(varint::sleb128(&[0xC0, 0xBB, 0x78]), Some((-123456, 3)));
+        assert_eq!(varint::unzigzag(3), -2);
+        assert_eq!(varint::vlq(&[0x81, 0x80, 0x00]), Some((0x4000, 3)));
+        assert_eq!(varint::git(&[0x80, 0x00]), Some((128, 2)));
+        assert_eq!(varint::prefix(&[0xC0, 0x01, 0x02]), Some((0x0102, 3)));
+        assert_eq!(varint::prefix(&[0x7F]), Some((0x7F, 1)));
+    }
+
+    #[test]
+    fn test_varint_overflow() {
+        let mut overflow = [0xFF; 12];
+        assert_eq!(varint::uleb128(&overflow), None);
+        assert_eq!(varint::vlq(&overflow), None);
+        assert_eq!(varint::git(&overflow), None);
+
+        // Ten groups hold 70 bits: too many unless the last adds only bit 63
+        overflow[9] = 0x7F;
+        assert_eq!(varint::uleb128(&overflow), None);
+        assert_eq!(varint::sleb128(&overflow), None);
+        assert_eq!(varint::vlq(&overflow), None);
+        assert_eq!(varint::git(&overflow), None);
+        overflow[9] = 0x01;
+        assert_eq!(varint::uleb128(&overflow), Some((u64::MAX, 10)));
+    }
+
+    #[test]
+    fn test_timestamp_types() {
+        let unix = 1_700_000_000u32.to_le_bytes();
+        assert_eq!(DataType::Unix32.decode(&unix), "2023-11-14T22:13:20Z");
+        let ms = 1_700_000_000_123i64.to_le_bytes();
+        assert_eq!(DataType::UnixMillis.decode(&ms), "2023-11-14T22:13:20.123Z");
+        let filetime = 133_444_736_000_000_000u64.to_le_bytes();
+        assert_eq!(DataType::FileTime.decode(&filetime), "2023-11-14T22:13:20Z");
+        // 2023-11-14 22:13:20 -> date 0x576E, time 0xB1AA
+        let dos = 0x576E_B1AAu32.to_le_bytes();
+        assert_eq!(DataType::DosDateTime.decode(&dos), "2023-11-14T22:13:20Z");
+        assert_eq!(
+            DataType::GpsTime.decode(&[0, 0, 0, 0]),
+            "1980-01-06T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_string_types() {
+        let cstr = DataType::from_name("utf8:nul").unwrap();
+        assert_eq!(
+            cstr.decode_sized(b"abc\0def"),
+            Some(("\"abc\"".to_string(), 4))
+        );
+        assert_eq!(cstr.decode_sized(b"abc"), None);
+
+        let pstr = DataType::from_name("utf16le:u8len").unwrap();
+        assert_eq!(pstr.name(), "utf16le:u8len");
+        assert_eq!(
+            pstr.decode_sized(&[2, b'h', 0, b'i', 0, 0xff]),
+            Some(("\"hi\"".to_string(), 5))
+        );
+
+        let fixed = DataType::from_name("ascii:fixed").unwrap();
+        assert_eq!(fixed.decode(b"ab\0\0"), "\"ab\"");
+
+        let blob = DataType::from_name("bytes:varlen").unwrap();
+        assert_eq!(
+            blob.decode_sized(&[2, 0xde, 0xad, 0x00]),
+            Some(("dead".to_string(), 3))
+        );
+    }
+}
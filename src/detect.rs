@@ -0,0 +1,188 @@
+//! Scored preset detection
+//!
+//! A preset is a candidate for a record when all of its detect rules match.
+//! Across many records, candidates are ranked by score (match rate times
+//! specificity), then specificity, then name, so the same input always picks
+//! the same preset and `lw detect --explain` can show why.
+
+use crate::preset::CompiledPreset;
+
+/// How well one preset matches a set of records
+#[derive(Debug)]
+pub struct Candidate<'a> {
+    pub name: &'a str,
+    pub preset: &'a CompiledPreset,
+    /// Records matching every rule
+    pub matched: usize,
+    pub total: usize,
+    /// Records matching each rule, in declaration order
+    pub rule_hits: Vec<usize>,
+}
+
+impl Candidate<'_> {
+    pub fn match_rate(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.matched as f64 / self.total as f64
+        }
+    }
+
+    pub fn score(&self) -> f64 {
+        self.match_rate() * self.preset.specificity()
+    }
+}
+
+/// Order by score, then specificity, then name
+fn by_rank(a: &Candidate, b: &Candidate) -> std::cmp::Ordering {
+    b.score()
+        .total_cmp(&a.score())
+        .then(b.preset.specificity().total_cmp(&a.preset.specificity()))
+        .then(a.name.cmp(b.name))
+}
+
+/// Score every preset with detect rules against the records, best first.
+/// Presets matching no records are included (with a score of zero) for explanations.
+pub fn rank<'a>(
+    presets: &[(&'a str, &'a CompiledPreset)],
+    records: &[&[u8]],
+) -> Vec<Candidate<'a>> {
+    let mut candidates: Vec<Candidate> = presets
+        .iter()
+        .filter(|(_, preset)| !preset.preset.detect.is_empty())
+        .map(|&(name, preset)| {
+            let mut rule_hits = vec![0; preset.preset.detect.len()];
+            let mut matched = 0;
+            for record in records {
+                let results = preset.rule_matches(record);
+                for (hits, ok) in rule_hits.iter_mut().zip(&results) {
+                    *hits += *ok as usize;
+                }
+                matched += results.iter().all(|ok| *ok) as usize;
+            }
+            Candidate {
+                name,
+                preset,
+                matched,
+                total: records.len(),
+                rule_hits,
+            }
+        })
+        .collect();
+    candidates.sort_by(by_rank);
+    candidates
+}
+
+/// Most specific preset matching a single record
+pub fn best<'a>(
+    presets: &[(&'a str, &'a CompiledPreset)],
+    record: &[u8],
+) -> Option<(&'a str, &'a CompiledPreset)> {
+    presets
+        .iter()
+        .filter(|(_, preset)| preset.matches(record))
+        .min_by(|(a_name, a), (b_name, b)| {
+            b.specificity()
+                .total_cmp(&a.specificity())
+                .then(a_name.cmp(b_name))
+        })
+        .copied()
+}
+
+/// Each candidate's share of the total score, as a percentage
+pub fn confidence(candidates: &[Candidate]) -> Vec<f64> {
+    let total: f64 = candidates.iter().map(Candidate::score).sum();
+    candidates
+        .iter()
+        .map(|c| {
+            if total > 0.0 {
+                100.0 * c.score() / total
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset::PresetManager;
+
+    #[test]
+    fn test_rank_is_deterministic() {
+        let compiled: Vec<(&str, CompiledPreset)> = PresetManager::embedded()
+            .iter()
+            .map(|(name, source)| {
+                let preset = toml::from_str(source).unwrap();
+                (*name, CompiledPreset::new(preset).unwrap())
+            })
+            .collect();
+        let mut presets: Vec<_> = compiled.iter().map(|(n, p)| (*n, p)).collect();
+
+        // Hex's character class is narrower, so it says more about "deadbeef"
+        let records: Vec<&[u8]> = vec![b"deadbeef", b"0011aabb"];
+        let ranked = rank(&presets, &records);
+        assert_eq!(ranked[0].name, "hex");
+        assert_eq!(ranked[0].matched, 2);
+        assert_eq!(best(&presets, b"deadbeef").map(|(n, _)| n), Some("hex"));
+
+        // Declaration order doesn't matter
+        presets.reverse();
+        let names: Vec<_> = rank(&presets, &records).iter().map(|c| c.name).collect();
+        let again: Vec<_> = ranked.iter().map(|c| c.name).collect();
+        assert_eq!(names, again);
+
+        let pct = confidence(&ranked);
+        assert!(pct[0] > pct[1]);
+        assert!((pct.iter().sum::<f64>() - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_encoding_presets_detect() {
+        let compiled: Vec<(&str, CompiledPreset)> = PresetManager::embedded()
+            .iter()
+            .map(|(name, source)| {
+                let preset = toml::from_str(source).unwrap();
+                (*name, CompiledPreset::new(preset).unwrap())
+            })
+            .collect();
+        let presets: Vec<_> = compiled.iter().map(|(n, p)| (*n, p)).collect();
+
+        let samples: &[(&[u8], &str)] = &[
+            (b"JBSWY3DPEHPK3PXP", "base32"),
+            (b"1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa", "base58"),
+            (b"SGVsbG8gV29ybGQ=", "base64"),
+            (b"SGVsbG8_-w", "base64url"),
+            (b">OwJh>}AQ;r@@Y?F", "base91"),
+            (b"87cURD]i,\"Ebo80", "ascii85"),
+            (b"caf%C3%A9%20au%2Flait", "url"),
+            (b"Caf=C3=A9 au lait", "quoted-printable"),
+        ];
+        for (record, expected) in samples {
+            assert_eq!(best(&presets, record).map(|(n, _)| n), Some(*expected));
+        }
+
+        // Log lines and identifiers that only look encoded
+        let negatives: &[(&[u8], &str)] = &[
+            (b"status=200 bytes=15", "quoted-printable"),
+            (b"request done (code=42)", "quoted-printable"),
+            (b"user_id", "base64url"),
+            (b"HTTP_STATUS_OK", "base64url"),
+            (b"function_name_here", "base64url"),
+            (b"function_name_here", "base91"),
+            (b"SGVsbG8_-", "base64url"),
+            (b"APPLICATIONSTARTED", "base32"),
+            (b"SERVICE2RESTARTED", "base32"),
+        ];
+        for (record, wrong) in negatives {
+            let (_, preset) = compiled.iter().find(|(n, _)| n == wrong).unwrap();
+            assert!(
+                !preset.matches(record),
+                "{} matched {:?}",
+                wrong,
+                String::from_utf8_lossy(record)
+            );
+        }
+    }
+}
@@ -16,156 +16,7 @@ use std::fs;
 use std::io;
 use std::path::Path;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum DataType {
-    U8,
-    U16Le,
-    U16Be,
-    U32Le,
-    U32Be,
-    VarInt,
-    Hex,
-    Binary,
-    Ascii,
-}
-
-impl DataType {
-    fn all() -> &'static [DataType] {
-        &[
-            DataType::U8,
-            DataType::U16Le,
-            DataType::U16Be,
-            DataType::U32Le,
-            DataType::U32Be,
-            DataType::VarInt,
-            DataType::Hex,
-            DataType::Binary,
-            DataType::Ascii,
-        ]
-    }
-
-    fn name(&self) -> &'static str {
-        match self {
-            DataType::U8 => "u8",
-            DataType::U16Le => "u16le",
-            DataType::U16Be => "u16be",
-            DataType::U32Le => "u32le",
-            DataType::U32Be => "u32be",
-            DataType::VarInt => "varint",
-            DataType::Hex => "hex",
-            DataType::Binary => "binary",
-            DataType::Ascii => "ascii",
-        }
-    }
-
-    fn byte_size(&self) -> Option<usize> {
-        match self {
-            DataType::U8 | DataType::Hex | DataType::Binary | DataType::Ascii => Some(1),
-            DataType::U16Le | DataType::U16Be => Some(2),
-            DataType::U32Le | DataType::U32Be => Some(4),
-            DataType::VarInt => None, // Variable
-        }
-    }
-
-    fn next(&self) -> DataType {
-        let all = Self::all();
-        let idx = all.iter().position(|t| t == self).unwrap_or(0);
-        all[(idx + 1) % all.len()]
-    }
-
-    fn prev(&self) -> DataType {
-        let all = Self::all();
-        let idx = all.iter().position(|t| t == self).unwrap_or(0);
-        all[(idx + all.len() - 1) % all.len()]
-    }
-
-    fn from_name(name: &str) -> Option<DataType> {
-        match name {
-            "u8" => Some(DataType::U8),
-            "u16le" => Some(DataType::U16Le),
-            "u16be" => Some(DataType::U16Be),
-            "u32le" => Some(DataType::U32Le),
-            "u32be" => Some(DataType::U32Be),
-            "varint" => Some(DataType::VarInt),
-            "hex" => Some(DataType::Hex),
-            "binary" => Some(DataType::Binary),
-            "ascii" => Some(DataType::Ascii),
-            _ => None,
-        }
-    }
-
-    fn decode(&self, data: &[u8]) -> String {
-        match self {
-            DataType::U8 => data.first().map(|&v| format!("{}", v)).unwrap_or_default(),
-            DataType::Hex => data
-                .first()
-                .map(|&v| format!("{:02x}", v))
-                .unwrap_or_default(),
-            DataType::Binary => data
-                .first()
-                .map(|&v| format!("{:08b}", v))
-                .unwrap_or_default(),
-            DataType::Ascii => data
-                .first()
-                .map(|&v| {
-                    if v.is_ascii_graphic() || v == b' ' {
-                        (v as char).to_string()
-                    } else {
-                        format!("\\x{:02x}", v)
-                    }
-                })
-                .unwrap_or_default(),
-            DataType::U16Le if data.len() >= 2 => {
-                format!("{}", u16::from_le_bytes([data[0], data[1]]))
-            }
-            DataType::U16Be if data.len() >= 2 => {
-                format!("{}", u16::from_be_bytes([data[0], data[1]]))
-            }
-            DataType::U32Le if data.len() >= 4 => {
-                format!(
-                    "{}",
-                    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
-                )
-            }
-            DataType::U32Be if data.len() >= 4 => {
-                format!(
-                    "{}",
-                    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
-                )
-            }
-            DataType::VarInt => Self::decode_varint(data),
-            _ => String::new(),
-        }
-    }
-
-    fn decode_varint(data: &[u8]) -> String {
-        let mut value: u64 = 0;
-        let mut shift = 0;
-        for &byte in data {
-            if shift >= 64 {
-                break;
-            }
-            value |= ((byte & 0x7F) as u64) << shift;
-            if byte & 0x80 == 0 {
-                return format!("{}", value);
-            }
-            shift += 7;
-        }
-        String::new()
-    }
-
-    fn display_width(&self) -> usize {
-        match self {
-            DataType::U8 => 4,                       // "255 "
-            DataType::Hex => 3,                      // "ff "
-            DataType::Binary => 9,                   // "00000000 "
-            DataType::U16Le | DataType::U16Be => 6,  // "65535 "
-            DataType::U32Le | DataType::U32Be => 11, // "4294967295 "
-            DataType::VarInt => 11,
-            DataType::Ascii => 2, // "X "
-        }
-    }
-}
+use crate::datatype::DataType;
 
 #[derive(Debug, Clone)]
 pub struct LockedField {
@@ -383,6 +234,7 @@ impl InteractiveState {
         content.push_str("# @rules\n");
         content.push_str("# byte_equals 0 33\n");
         content.push_str("# min_length 30\n");
+        content.push_str("# bytes 0 21 ?? 00 ff\n");
 
         fs::write(&path, content).map_err(|e| format!("Failed to save: {}", e))
     }
@@ -621,8 +473,27 @@ impl InteractiveState {
     /// count specifies how many consecutive fields to lock as one region
     fn lock_current(&mut self, count: usize) {
         let byte_off = self.current_field_byte();
-        let type_size = self.current_type.byte_size().unwrap_or(1);
-        let byte_len = type_size * count;
+        let record_len = self
+            .records
+            .get(self.current_record)
+            .map(|r| r.len())
+            .unwrap_or(0);
+
+        // Variable-length types lock the width they decode to in the current record
+        let byte_len = match self.current_type.byte_size() {
+            Some(type_size) => type_size * count,
+            None => match self.variable_width(byte_off, count) {
+                Some(len) => len,
+                None => {
+                    self.message = Some(format!(
+                        "Cannot lock: no complete {} at byte {}",
+                        self.current_type.name(),
+                        byte_off
+                    ));
+                    return;
+                }
+            },
+        };
 
         // Check for overlap with existing locked fields
         let overlaps = self.locked_fields.iter().any(|f| {
@@ -637,11 +508,6 @@ impl InteractiveState {
         }
 
         // Check if we have enough bytes in the record
-        let record_len = self
-            .records
-            .get(self.current_record)
-            .map(|r| r.len())
-            .unwrap_or(0);
         if byte_off + byte_len > record_len {
             self.message = Some(format!(
                 "Cannot lock: {} bytes needed, only {} available",
@@ -675,6 +541,17 @@ impl InteractiveState {
         }
     }
 
+    /// Total width of `count` consecutive variable-length values of the
+    /// current type, starting at `byte_off` in the current record
+    fn variable_width(&self, byte_off: usize, count: usize) -> Option<usize> {
+        let record = self.records.get(self.current_record)?;
+        let mut pos = byte_off;
+        for _ in 0..count {
+            pos += self.current_type.width_at(record.get(pos..)?)?;
+        }
+        Some(pos - byte_off)
+    }
+
     /// Unlock the field at the cursor position
     fn unlock_at_cursor(&mut self) {
         let byte_off = self.current_field_byte();
@@ -1138,11 +1015,11 @@ fn draw_records(f: &mut Frame, area: Rect, state: &mut InteractiveState) {
 
             let (display_value, display_type, advance_by) = if let Some(lf) = locked_field {
                 // Use locked field's type for display
-                let val = decode_value(record, lf.byte_offset, lf.data_type);
+                let val = decode_value(record, lf.byte_offset, lf.byte_length, lf.data_type);
                 (val, lf.data_type, lf.byte_length)
             } else {
                 // Use current type
-                let val = decode_value(record, byte_pos, state.current_type);
+                let val = decode_value(record, byte_pos, type_size, state.current_type);
                 (val, state.current_type, type_size)
             };
 
@@ -1204,10 +1081,16 @@ fn draw_records(f: &mut Frame, area: Rect, state: &mut InteractiveState) {
     }
 }
 
-/// Decode a value from the record at the given byte offset
-fn decode_value(record: &[u8], byte_off: usize, dtype: DataType) -> String {
+/// Decode a value from the record at the given byte offset.
+/// Fixed-size types only see `byte_len` bytes so whole-field strings stop at the field end;
+/// variable-length types see the rest of the record and find their own end.
+fn decode_value(record: &[u8], byte_off: usize, byte_len: usize, dtype: DataType) -> String {
+    let end = match dtype.byte_size() {
+        Some(_) => (byte_off + byte_len).min(record.len()),
+        None => record.len(),
+    };
     record
-        .get(byte_off..)
+        .get(byte_off..end)
         .map(|data| dtype.decode(data))
         .unwrap_or_default()
 }
@@ -1246,8 +1129,16 @@ fn format_field_value(value: &str, dtype: DataType) -> String {
         DataType::Binary => 8,                   // 8 bits
         DataType::U16Le | DataType::U16Be => 5,  // 0-65535
         DataType::U32Le | DataType::U32Be => 10, // 0-4294967295
-        DataType::VarInt => 10,                  // variable, but cap display
-        DataType::Ascii => 1,                    // single character
+        DataType::VarInt | DataType::VarIntBe | DataType::GitVarInt | DataType::PrefixVarInt => 10, // variable, but cap display
+        DataType::SLeb128 | DataType::ZigZag => 11, // sign
+        DataType::Ascii => 1,                       // single character
+        DataType::Unix32
+        | DataType::Unix64
+        | DataType::FileTime
+        | DataType::DosDateTime
+        | DataType::GpsTime => 20, // ISO-8601 seconds
+        DataType::UnixMillis => 24,                 // ISO-8601 milliseconds
+        DataType::Str(..) | DataType::Bytes(_) => 1, // whole value, unpadded
     };
     format!("{:>width$}", value, width = width)
 }
@@ -1303,3 +1194,18 @@ fn draw_status_bar(f: &mut Frame, area: Rect, state: &InteractiveState) {
     let widget = Paragraph::new(Line::from(spans));
     f.render_widget(widget, area);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_varint_width() {
+        let mut state = InteractiveState::new(vec![vec![0xAC, 0x02, 0x05, 0x01]]);
+        state.current_type = DataType::VarInt;
+        state.lock_current(2);
+        assert_eq!(state.locked_fields.len(), 1);
+        assert_eq!(state.locked_fields[0].byte_length, 3);
+        assert_eq!(DataType::VarInt.decode(&[0xAC, 0x02]), "300");
+    }
+}
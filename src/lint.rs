@@ -0,0 +1,764 @@
+//! Static validation of preset files (`lw preset lint`)
+//!
+//! Checks everything that would otherwise fail silently at match time:
+//! regexes that don't compile, unknown transforms, charsets and data types,
+//! overlapping layout fields and detect rules that can never all match.
+//! Issues are located by line and column in the preset's TOML source.
+
+use crate::datatype::{DataType, Termination};
+use crate::plugin;
+use crate::preset::{
+    base85_charsets, parse_byte_pattern, parse_xor_key, DetectRule, JsonPath, Preset,
+    PresetManager, RecordFormat, SegmentMode, BUILTIN_TRANSFORMS, FALLBACKS,
+};
+use crate::script;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem found in a preset
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// 1-based line and column in the source, if the value could be located
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+/// Step in a path to a value in the preset document
+#[derive(Debug, Clone, Copy)]
+enum Key<'a> {
+    Name(&'a str),
+    Index(usize),
+}
+
+/// Collects issues for one preset source, resolving paths to locations
+struct Linter<'a> {
+    source: &'a str,
+    /// Directory of the preset file, which `wasm` paths are relative to
+    dir: Option<&'a Path>,
+    doc: Option<toml_edit::ImDocument<&'a str>>,
+    issues: Vec<Issue>,
+}
+
+impl<'a> Linter<'a> {
+    fn new(source: &'a str, dir: Option<&'a Path>) -> Self {
+        Self {
+            source,
+            dir,
+            doc: toml_edit::ImDocument::parse(source).ok(),
+            issues: Vec::new(),
+        }
+    }
+
+    /// Line and column of a byte offset
+    fn line_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.source[..offset.min(self.source.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+        (line, col)
+    }
+
+    /// Find a value by path, falling back to the nearest enclosing item that exists.
+    /// Inherited values that aren't in this file have no location.
+    fn locate(&self, path: &[Key]) -> Option<(usize, usize)> {
+        let mut item = self.doc.as_ref()?.as_item();
+        let mut span = None;
+        for key in path {
+            let next = match key {
+                Key::Name(name) => item.get(*name),
+                Key::Index(idx) => item.get(*idx),
+            };
+            let Some(next) = next else { break };
+            item = next;
+            span = item.span().or(span);
+        }
+        span.map(|s| self.line_col(s.start))
+    }
+
+    fn report(&mut self, severity: Severity, path: &[Key], message: String) {
+        let location = self.locate(path);
+        self.issues.push(Issue {
+            severity,
+            location,
+            message,
+        });
+    }
+
+    fn error(&mut self, path: &[Key], message: String) {
+        self.report(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: &[Key], message: String) {
+        self.report(Severity::Warning, path, message);
+    }
+
+    fn check_regex(&mut self, path: &[Key], pattern: &str) {
+        if let Err(e) = regex::Regex::new(pattern) {
+            let what = path.iter().map(key_name).collect::<Vec<_>>().join("");
+            // Syntax errors draw a caret under the pattern; keep just the reason
+            let message = e.to_string();
+            let reason = message.lines().last().unwrap_or_default().trim();
+            let reason = reason.strip_prefix("error: ").unwrap_or(reason);
+            self.error(path, format!("invalid regex in {}: {}", what, reason));
+        }
+    }
+
+    fn check_script(&mut self, path: &[Key], source: &str) {
+        if let Err(e) = script::compile(source) {
+            self.error(path, format!("{:#}", e));
+        }
+    }
+
+    /// A file named in the preset, relative to the preset's directory
+    fn resolve(&self, file: &Path) -> PathBuf {
+        match self.dir {
+            Some(dir) => dir.join(file),
+            None => file.to_path_buf(),
+        }
+    }
+
+    fn check_plugin(&mut self, path: &[Key], wasm: &Path) {
+        if let Err(e) = plugin::load(&self.resolve(wasm)) {
+            self.error(path, format!("{:#}", e));
+        }
+    }
+}
+
+fn key_name(key: &Key) -> String {
+    match key {
+        Key::Name(name) => format!(".{}", name),
+        Key::Index(idx) => format!("[{}]", idx),
+    }
+}
+
+/// Lint a preset's TOML source, read from a file in `dir` if any. `resolved`
+/// is the preset after `extends` inheritance, when the source declares a parent.
+pub fn lint_source(
+    source: &str,
+    dir: Option<&Path>,
+    resolved: Option<Result<Preset>>,
+) -> Vec<Issue> {
+    let mut linter = Linter::new(source, dir);
+
+    let preset = match resolved {
+        Some(Ok(preset)) => preset,
+        Some(Err(e)) => {
+            linter.error(
+                &[Key::Name("preset"), Key::Name("extends")],
+                format!("{:#}", e),
+            );
+            return linter.issues;
+        }
+        None => match toml::from_str::<Preset>(source) {
+            Ok(preset) => preset,
+            Err(e) => {
+                let location = e.span().map(|s| linter.line_col(s.start));
+                linter.issues.push(Issue {
+                    severity: Severity::Error,
+                    location,
+                    message: e.message().trim().replace('\n', ": "),
+                });
+                return linter.issues;
+            }
+        },
+    };
+
+    if let RecordFormat::Custom { pattern } = &preset.records {
+        linter.check_regex(&[Key::Name("records"), Key::Name("pattern")], pattern);
+    }
+
+    check_detect(&mut linter, &preset.detect);
+    check_gloss(&mut linter, &preset);
+
+    for (i, rule) in preset.color.iter().enumerate() {
+        linter.check_regex(
+            &[Key::Name("color"), Key::Index(i), Key::Name("match")],
+            &rule.pattern,
+        );
+    }
+    for (i, field) in preset.fields.iter().enumerate() {
+        let at = |key| [Key::Name("fields"), Key::Index(i), Key::Name(key)];
+        match (&field.script, &field.json) {
+            (Some(source), json) => {
+                linter.check_script(&at("script"), source);
+                if json.is_some() || !field.pattern.is_empty() || field.wasm.is_some() {
+                    linter.warning(
+                        &at("script"),
+                        "pattern, json and wasm are ignored because script is set".to_string(),
+                    );
+                }
+            }
+            (None, json) if field.wasm.is_some() => {
+                linter.check_plugin(&at("wasm"), field.wasm.as_deref().unwrap());
+                if json.is_some() || !field.pattern.is_empty() {
+                    linter.warning(
+                        &at("wasm"),
+                        "pattern and json are ignored because wasm is set".to_string(),
+                    );
+                }
+            }
+            (None, Some(path)) => {
+                if let Err(e) = JsonPath::parse(path) {
+                    linter.error(&at("json"), format!("invalid JSON path {:?}: {}", path, e));
+                }
+                if !field.pattern.is_empty() {
+                    linter.warning(
+                        &at("pattern"),
+                        "pattern is ignored because json is set".to_string(),
+                    );
+                }
+            }
+            (None, None) if field.pattern.is_empty() => linter.warning(
+                &[Key::Name("fields"), Key::Index(i)],
+                format!(
+                    "field '{}' has no pattern, json, script or wasm",
+                    field.name
+                ),
+            ),
+            (None, None) => linter.check_regex(&at("pattern"), &field.pattern),
+        }
+    }
+
+    check_layout(&mut linter, &preset);
+    linter.issues
+}
+
+fn check_gloss(linter: &mut Linter, preset: &Preset) {
+    let Some(gloss) = &preset.gloss else {
+        return;
+    };
+    let at = |key| [Key::Name("gloss"), Key::Name(key)];
+
+    match &gloss.segment {
+        Some(segment) => linter.check_regex(&at("segment"), segment),
+        None if gloss.segments != SegmentMode::First => linter.warning(
+            &at("segments"),
+            "segments has no effect without a segment pattern".to_string(),
+        ),
+        None => {}
+    }
+    check_step(
+        linter,
+        &[Key::Name("gloss")],
+        gloss.transform.as_deref(),
+        gloss.base85_charset.as_deref(),
+        gloss.command.as_deref(),
+        gloss.jwt_key.as_deref(),
+        gloss.xor_key.as_deref(),
+    );
+    if let Some(source) = &gloss.transform_script {
+        linter.check_script(&at("transform_script"), source);
+    }
+    if let Some(wasm) = &gloss.wasm {
+        linter.check_plugin(&at("wasm"), wasm);
+    }
+    let single =
+        gloss.transform.is_some() || gloss.transform_script.is_some() || gloss.wasm.is_some();
+    if !gloss.steps.is_empty() && (single || gloss.command.is_some()) {
+        linter.warning(
+            &at("steps"),
+            "transform, transform_script, wasm and command are ignored because steps are set"
+                .to_string(),
+        );
+    }
+    let runs_command = gloss.steps.is_empty() && !single;
+    if gloss.server.is_some() && !(runs_command && gloss.command.is_some()) {
+        linter.warning(
+            &at("server"),
+            "server is ignored unless command runs (without transform or steps)".to_string(),
+        );
+    }
+    if gloss.timeout_ms == Some(0) {
+        linter.error(&at("timeout_ms"), "timeout_ms must be positive".to_string());
+    }
+    for (i, step) in gloss.steps.iter().enumerate() {
+        let path = [Key::Name("gloss"), Key::Name("steps"), Key::Index(i)];
+        match (&step.transform_script, &step.wasm) {
+            (Some(source), _) => linter.check_script(
+                &[&path[..], &[Key::Name("transform_script")]].concat(),
+                source,
+            ),
+            (None, Some(wasm)) => {
+                linter.check_plugin(&[&path[..], &[Key::Name("wasm")]].concat(), wasm)
+            }
+            (None, None) if step.transform.is_none() && step.command.is_none() => {
+                linter.warning(
+                    &path,
+                    "step has no transform, script, wasm or command".to_string(),
+                );
+            }
+            (None, None) => {}
+        }
+        check_step(
+            linter,
+            &path,
+            step.transform.as_deref(),
+            step.base85_charset.as_deref(),
+            step.command.as_deref(),
+            step.jwt_key.as_deref(),
+            step.xor_key
+                .as_ref()
+                .or(gloss.xor_key.as_ref())
+                .map(String::as_str),
+        );
+    }
+    if let Some(fallback) = &gloss.fallback {
+        if !FALLBACKS.contains(&fallback.as_str()) {
+            linter.error(
+                &at("fallback"),
+                format!(
+                    "unknown fallback '{}' (expected one of: {})",
+                    fallback,
+                    FALLBACKS.join(", ")
+                ),
+            );
+        }
+    }
+}
+
+/// Check the transform, charset and command of `[gloss]` or one of its steps
+fn check_step(
+    linter: &mut Linter,
+    path: &[Key],
+    transform: Option<&str>,
+    charset: Option<&str>,
+    command: Option<&[String]>,
+    jwt_key: Option<&Path>,
+    xor_key: Option<&str>,
+) {
+    let at = |key| [path, &[Key::Name(key)]].concat();
+
+    if let Some(transform) = transform {
+        if !transform.is_empty() && !BUILTIN_TRANSFORMS.contains(&transform) {
+            linter.error(
+                &at("transform"),
+                format!(
+                    "unknown transform '{}' (expected one of: {})",
+                    transform,
+                    BUILTIN_TRANSFORMS.join(", ")
+                ),
+            );
+        }
+        if command.is_some() {
+            linter.warning(
+                &at("command"),
+                "command is ignored because transform is set".to_string(),
+            );
+        }
+        if transform.starts_with("xor") {
+            if let Err(e) = parse_xor_key(xor_key) {
+                linter.error(&at("xor_key"), format!("{:#}", e));
+            }
+        }
+    }
+    if let Some(charset) = charset {
+        if base85_charsets::get(charset).is_none() {
+            linter.error(
+                &at("base85_charset"),
+                format!(
+                    "unknown base85 charset '{}' (expected ascii85, z85 or bl4)",
+                    charset
+                ),
+            );
+        }
+    }
+    if command.is_some_and(|cmd| cmd.is_empty()) {
+        linter.error(&at("command"), "command is empty".to_string());
+    }
+    if let Some(key) = jwt_key {
+        if !linter.resolve(key).is_file() {
+            linter.warning(&at("jwt_key"), format!("JWT key file {:?} not found", key));
+        }
+    }
+}
+
+/// Check detect rules compile and can be satisfied together
+fn check_detect(linter: &mut Linter, rules: &[DetectRule]) {
+    let at = |i: usize, key| [Key::Name("detect"), Key::Index(i), Key::Name(key)];
+
+    let min_len = rules
+        .iter()
+        .filter_map(|r| match r {
+            DetectRule::MinLength { value } | DetectRule::LengthEquals { value } => Some(*value),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    let max_len = rules
+        .iter()
+        .filter_map(|r| match r {
+            DetectRule::MaxLength { value } | DetectRule::LengthEquals { value } => Some(*value),
+            _ => None,
+        })
+        .min();
+
+    // Bytes pinned by starts_with/byte_equals, to find contradictions
+    let mut pinned: Vec<(usize, u8, usize)> = Vec::new();
+    let mut suffix: Option<(usize, &str)> = None;
+
+    for (i, rule) in rules.iter().enumerate() {
+        let unreachable = |what: String| format!("unreachable detect rule: {}", what);
+        check_rule(linter, &[Key::Name("detect"), Key::Index(i)], rule);
+        match rule {
+            DetectRule::MaxLength { value } | DetectRule::LengthEquals { value }
+                if *value < min_len =>
+            {
+                linter.error(
+                    &at(i, "value"),
+                    unreachable(format!(
+                        "{} but length must be at least {}",
+                        rule.describe(),
+                        min_len
+                    )),
+                );
+            }
+            DetectRule::StartsWith { value }
+            | DetectRule::EndsWith { value }
+            | DetectRule::Contains { value }
+                if max_len.is_some_and(|max| value.len() > max) =>
+            {
+                linter.error(
+                    &at(i, "value"),
+                    unreachable(format!(
+                        "{:?} is longer than max_length {}",
+                        value,
+                        max_len.unwrap_or(0)
+                    )),
+                );
+            }
+            DetectRule::ByteEquals { position, .. }
+                if max_len.is_some_and(|max| *position >= max) =>
+            {
+                linter.error(
+                    &at(i, "position"),
+                    unreachable(format!(
+                        "position {} is beyond max_length {}",
+                        position,
+                        max_len.unwrap_or(0)
+                    )),
+                );
+            }
+            _ => {}
+        }
+
+        let new_pins: Vec<(usize, u8)> = match rule {
+            DetectRule::StartsWith { value } => value.bytes().enumerate().collect(),
+            DetectRule::ByteEquals { position, value } => vec![(*position, *value)],
+            DetectRule::Bytes {
+                pattern,
+                offset: Some(offset),
+            } => parse_byte_pattern(pattern)
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+                .filter_map(|(j, byte)| byte.map(|b| (offset + j, b)))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for (pos, byte) in new_pins {
+            if let Some(&(_, other, j)) = pinned.iter().find(|(p, b, _)| *p == pos && *b != byte) {
+                let key = match rule {
+                    DetectRule::ByteEquals { .. } => "position",
+                    DetectRule::Bytes { .. } => "pattern",
+                    _ => "value",
+                };
+                linter.error(
+                    &at(i, key),
+                    unreachable(format!(
+                        "byte {} must be 0x{:02x} but detect[{}] requires 0x{:02x}",
+                        pos, byte, j, other
+                    )),
+                );
+                break;
+            }
+            pinned.push((pos, byte, i));
+        }
+
+        if let DetectRule::EndsWith { value } = rule {
+            match suffix {
+                Some((j, other)) if !value.ends_with(other) && !other.ends_with(value.as_str()) => {
+                    linter.error(
+                        &at(i, "value"),
+                        unreachable(format!(
+                            "ends_with {:?} conflicts with detect[{}] ends_with {:?}",
+                            value, j, other
+                        )),
+                    );
+                }
+                Some((_, other)) if other.len() >= value.len() => {}
+                _ => suffix = Some((i, value)),
+            }
+        }
+    }
+}
+
+/// Check a rule's own values, recursing into `any`, `all` and `not`
+fn check_rule(linter: &mut Linter, path: &[Key], rule: &DetectRule) {
+    let at = |key| [path, &[Key::Name(key)]].concat();
+    match rule {
+        DetectRule::Regex { pattern } => linter.check_regex(&at("pattern"), pattern),
+        DetectRule::Bytes { pattern, .. } => {
+            if let Err(e) = parse_byte_pattern(pattern) {
+                linter.error(
+                    &at("pattern"),
+                    format!("invalid byte pattern {:?}: {}", pattern, e),
+                );
+            }
+        }
+        DetectRule::LengthModulo { modulus: 0, .. } => {
+            linter.error(
+                &at("modulus"),
+                "length_modulo modulus must be positive".to_string(),
+            );
+        }
+        DetectRule::LengthModulo { modulus, remainder } if remainder >= modulus => {
+            linter.error(
+                &at("remainder"),
+                format!(
+                    "unreachable detect rule: remainder {} is not below modulus {}",
+                    remainder, modulus
+                ),
+            );
+        }
+        DetectRule::Any { rules } | DetectRule::All { rules } => {
+            if rules.is_empty() {
+                let message = match rule {
+                    DetectRule::Any { .. } => "unreachable detect rule: empty any never matches",
+                    _ => "empty all always matches",
+                };
+                let severity = match rule {
+                    DetectRule::Any { .. } => Severity::Error,
+                    _ => Severity::Warning,
+                };
+                linter.report(severity, &at("rules"), message.to_string());
+            }
+            for (j, nested) in rules.iter().enumerate() {
+                let nested_path = [path, &[Key::Name("rules"), Key::Index(j)]].concat();
+                check_rule(linter, &nested_path, nested);
+            }
+        }
+        DetectRule::Not { rule } => check_rule(linter, &at("rule"), rule),
+        DetectRule::Script { script, .. } => linter.check_script(&at("script"), script),
+        _ => {}
+    }
+}
+
+/// Check layout types exist and fields don't overlap
+fn check_layout(linter: &mut Linter, preset: &Preset) {
+    let at = |i: usize, key| [Key::Name("layout"), Key::Index(i), Key::Name(key)];
+    let mut extents: Vec<(usize, usize, usize)> = Vec::new();
+
+    for (i, field) in preset.layout.iter().enumerate() {
+        let Some(dtype) = DataType::from_name(&field.data_type) else {
+            linter.error(
+                &at(i, "type"),
+                format!("unknown data type '{}'", field.data_type),
+            );
+            continue;
+        };
+
+        let fixed = matches!(
+            dtype,
+            DataType::Str(_, Termination::Fixed) | DataType::Bytes(Termination::Fixed)
+        );
+        if fixed && field.length.is_none() {
+            linter.error(
+                &at(i, "type"),
+                format!("'{}' needs a length", field.data_type),
+            );
+        }
+
+        // Only fields with a known width can be checked for overlap
+        let Some(width) = field.length.or(dtype.byte_size().filter(|_| !fixed)) else {
+            continue;
+        };
+        let (start, end) = (field.offset, field.offset.saturating_add(width));
+        if let Some(&(_, _, j)) = extents.iter().find(|(s, e, _)| start < *e && *s < end) {
+            linter.error(
+                &at(i, "offset"),
+                format!(
+                    "layout field '{}' (bytes {}..{}) overlaps '{}'",
+                    field.name, start, end, preset.layout[j].name
+                ),
+            );
+        }
+        extents.push((start, end, i));
+    }
+}
+
+/// Lint a preset by name or path (all embedded and user presets if none given).
+/// Prints compiler-style diagnostics and fails if any errors were found.
+pub fn preset_lint(target: Option<&str>) -> Result<()> {
+    let file = target.map(PathBuf::from).filter(|p| p.is_file());
+
+    // A file that fails to load is still linted, just without inheritance
+    let mut mgr = PresetManager::new();
+    match &file {
+        Some(path) if mgr.load_all_with(&[path.as_path()]).is_ok() => {}
+        _ => mgr.load_all()?,
+    }
+
+    // (display name, preset name, source, directory)
+    let mut sources: Vec<(String, Option<String>, String, Option<PathBuf>)> = Vec::new();
+    let add_file = |path: &Path, sources: &mut Vec<_>| -> Result<()> {
+        let content = fs::read_to_string(path)?;
+        let name = mgr.name_for(path).map(String::from);
+        let dir = path.parent().map(Path::to_path_buf);
+        sources.push((path.display().to_string(), name, content, dir));
+        Ok(())
+    };
+    let add_embedded = |name: &str, sources: &mut Vec<_>| {
+        if let Some((_, content)) = PresetManager::embedded().iter().find(|(n, _)| *n == name) {
+            sources.push((
+                format!("<embedded>/{}.toml", name),
+                None,
+                content.to_string(),
+                None,
+            ));
+            true
+        } else {
+            false
+        }
+    };
+
+    if let Some(path) = &file {
+        add_file(path, &mut sources)?;
+    } else if let Some(target) = target {
+        if let Some(path) = mgr.path(target) {
+            add_file(path, &mut sources)?;
+        } else if !add_embedded(target, &mut sources) {
+            anyhow::bail!("unknown preset '{}'", target);
+        }
+    } else {
+        for (name, _) in PresetManager::embedded() {
+            add_embedded(name, &mut sources);
+        }
+        for path in mgr.preset_files() {
+            add_file(&path, &mut sources)?;
+        }
+    }
+
+    let mut errors = 0;
+    let mut warnings = 0;
+    for (display, name, content, dir) in &sources {
+        let declares_parent = toml::from_str::<toml::Value>(content)
+            .ok()
+            .is_some_and(|v| v.get("preset").and_then(|p| p.get("extends")).is_some());
+        let resolved = match name {
+            Some(name) if declares_parent => Some(mgr.resolve(name)),
+            _ => None,
+        };
+
+        for issue in lint_source(content, dir.as_deref(), resolved) {
+            let (label, count) = match issue.severity {
+                Severity::Error => ("error", &mut errors),
+                Severity::Warning => ("warning", &mut warnings),
+            };
+            *count += 1;
+            match issue.location {
+                Some((line, col)) => {
+                    println!("{}:{}:{}: {}: {}", display, line, col, label, issue.message)
+                }
+                None => println!("{}: {}: {}", display, label, issue.message),
+            }
+        }
+    }
+
+    println!(
+        "{} preset(s) checked: {} error(s), {} warning(s)",
+        sources.len(),
+        errors,
+        warnings
+    );
+    if errors > 0 {
+        anyhow::bail!("preset lint found {} error(s)", errors);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_locates_issues() {
+        let source = r#"[preset]
+name = "bad"
+
+[[detect]]
+type = "min_length"
+value = 30
+
+[[detect]]
+type = "max_length"
+value = 10
+
+[[detect]]
+type = "regex"
+pattern = "(unclosed"
+
+[gloss]
+transform = "base58x"
+
+[[layout]]
+name = "a"
+offset = 0
+type = "u32le"
+
+[[layout]]
+name = "b"
+offset = 2
+type = "u16le"
+"#;
+        let issues = lint_source(source, None, None);
+        let located: Vec<_> = issues
+            .iter()
+            .map(|i| (i.location, i.message.split(':').next().unwrap_or("")))
+            .collect();
+        assert_eq!(
+            located,
+            vec![
+                (Some((10, 9)), "unreachable detect rule"),
+                (Some((14, 11)), "invalid regex in .detect[2].pattern"),
+                (
+                    Some((17, 13)),
+                    "unknown transform 'base58x' (expected one of"
+                ),
+                (Some((26, 10)), "layout field 'b' (bytes 2..4) overlaps 'a'"),
+            ]
+        );
+
+        let issues = lint_source("[preset]\nname = 1\n", None, None);
+        assert_eq!(issues[0].location, Some((2, 8)));
+    }
+
+    #[test]
+    fn test_lint_resolves_key_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("secret.key"), "secret").unwrap();
+        let source = |key: &str| {
+            format!(
+                "[preset]\nname = \"jwt\"\n\n[gloss]\ntransform = \"jwt\"\njwt_key = \"{key}\"\n"
+            )
+        };
+
+        assert!(lint_source(&source("secret.key"), Some(dir.path()), None).is_empty());
+        let issues = lint_source(&source("missing.key"), Some(dir.path()), None);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].location, Some((6, 11)));
+
+        let mut parsed: toml::Value = toml::from_str(&source("secret.key")).unwrap();
+        crate::preset::resolve_file_paths(&mut parsed, dir.path());
+        assert_eq!(
+            parsed["gloss"]["jwt_key"].as_str(),
+            dir.path().join("secret.key").to_str()
+        );
+    }
+}
@@ -1,9 +1,17 @@
 mod analysis;
+mod cache;
 mod commands;
 mod config;
+mod coprocess;
+mod datatype;
+mod detect;
 mod interactive;
+mod lint;
+mod output;
+mod plugin;
 mod preset;
 mod records;
+mod script;
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
@@ -11,6 +19,7 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Parser)]
 #[command(name = "lw")]
@@ -50,6 +59,19 @@ struct Cli {
     #[arg(short = 'a', long = "align", global = true)]
     align: bool,
 
+    /// Write records, preset names and fields as JSON lines, CSV or TSV with a header
+    #[arg(long = "output", global = true, value_enum, conflicts_with = "align")]
+    output: Option<output::OutputFormat>,
+
+    /// Gloss this many records at once (default: number of CPUs)
+    #[arg(short = 'j', long = "jobs", global = true)]
+    jobs: Option<usize>,
+
+    /// Sample records randomly with this seed when auto-detecting presets
+    /// (default: evenly spaced records)
+    #[arg(long = "seed", global = true)]
+    seed: Option<u64>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -177,6 +199,24 @@ enum Command {
         max_positions: usize,
     },
 
+    /// Guess the length and bytes of a repeating XOR key
+    Xorkey {
+        /// Input file
+        input: PathBuf,
+
+        /// Input format
+        #[arg(short, long, default_value = "length16")]
+        format: String,
+
+        /// Longest key length to try
+        #[arg(short = 'n', long, default_value = "32")]
+        max_len: usize,
+
+        /// Expected plaintext: 'zeros', 'text', or a file of sample plaintext
+        #[arg(long, default_value = "zeros")]
+        plaintext: String,
+    },
+
     /// Split records into separate files by header bytes
     Split {
         /// Input file
@@ -247,7 +287,7 @@ enum Command {
         #[arg(short, long)]
         preset: Option<String>,
 
-        /// Built-in transform: base85, base64, hex
+        /// Built-in transform: base85, base64, base32, base58, url, hex, ...
         #[arg(short, long)]
         transform: Option<String>,
 
@@ -256,8 +296,80 @@ enum Command {
         command: Option<String>,
     },
 
+    /// Encode hex (as printed by gloss) back to records, reversing a preset's transforms
+    Encode {
+        /// Input file (or - for stdin)
+        input: PathBuf,
+
+        /// Preset whose gloss transforms to reverse
+        #[arg(long)]
+        preset: Option<String>,
+
+        /// Built-in transform: base85, base64, base32, base58, url, hex, ...
+        #[arg(short, long)]
+        transform: Option<String>,
+
+        /// Base85 charset for --transform base85: ascii85, z85, bl4
+        #[arg(long)]
+        charset: Option<String>,
+
+        /// Read encoded records and check that decoding then encoding reproduces each one
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Rank presets against input lines and show the best match
+    Detect {
+        /// Input file (or - for stdin)
+        input: PathBuf,
+
+        /// Show which detect rules matched for every preset
+        #[arg(long)]
+        explain: bool,
+    },
+
     /// List available presets
     Presets,
+
+    /// Validate presets
+    Preset {
+        #[command(subcommand)]
+        action: PresetAction,
+    },
+
+    /// Manage the gloss result cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Delete all cached gloss results
+    Clear,
+
+    /// Show where the cache is stored and how big it is
+    Stats,
+}
+
+#[derive(Subcommand)]
+enum PresetAction {
+    /// Run a preset's [[test]] fixtures (from the preset, a <name>.tests.toml sidecar, or --fixture files)
+    Test {
+        /// Preset name or path to a preset file
+        preset: String,
+
+        /// Extra fixture files containing [[test]] entries
+        #[arg(long = "fixture")]
+        fixtures: Vec<PathBuf>,
+    },
+
+    /// Check presets for invalid regexes, unknown transforms and types, and unreachable rules
+    Lint {
+        /// Preset name or path (default: all embedded and installed presets)
+        preset: Option<String>,
+    },
 }
 
 fn read_records(path: &PathBuf, format: &str) -> Result<Vec<Vec<u8>>> {
@@ -377,6 +489,23 @@ fn analyze(records: &[Vec<u8>], max_positions: usize, show_bits: bool) {
             print_bit_analysis(records, pos);
         }
     }
+
+    let timestamps = analysis::find_timestamps(&record_refs, positions, 90);
+    if !timestamps.is_empty() {
+        println!("\nPossible timestamps:\n");
+        println!("{:>4}  {:<10}  {:>6}  Range", "Pos", "Type", "Match");
+        println!("{}", "-".repeat(70));
+        for ts in timestamps {
+            println!(
+                "{:>4}  {:<10}  {:>5}%  {} .. {}",
+                ts.position,
+                ts.data_type.name(),
+                ts.matches * 100 / ts.total,
+                ts.data_type.decode(&ts.earliest),
+                ts.data_type.decode(&ts.latest)
+            );
+        }
+    }
 }
 
 fn ngrams(records: &[Vec<u8>], size: usize, min_count: usize) {
@@ -392,7 +521,7 @@ fn ngrams(records: &[Vec<u8>], size: usize, min_count: usize) {
     }
 
     let mut pairs: Vec<_> = freq.into_iter().filter(|(_, c)| *c >= min_count).collect();
-    pairs.sort_by(|a, b| b.1.cmp(&a.1));
+    pairs.sort_by_key(|p| std::cmp::Reverse(p.1));
 
     println!("Top {}-grams (min count {}):", size, min_count);
     println!("{:>8}  Bytes", "Count");
@@ -441,7 +570,7 @@ fn entropy_analysis(records: &[Vec<u8>], max_positions: usize) {
 
         // Visual bar
         let bar_len = (entropy * 8.0) as usize;
-        let bar: String = "#".repeat(bar_len) + &" ".repeat(64 - bar_len);
+        let bar: String = "#".repeat(bar_len) + " ".repeat(64 - bar_len).as_str();
 
         println!(
             "{:>3}: [{:.2}] |{}|",
@@ -512,6 +641,13 @@ fn most_common(values: &[u8]) -> (u8, usize) {
 #[allow(clippy::too_many_lines)] // CLI command dispatch
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let sampling = cli
+        .seed
+        .map_or(config::Sampling::Stratified, config::Sampling::Seeded);
+
+    let jobs = cli
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
 
     // Handle -i flag for interactive mode
     if cli.interactive {
@@ -521,7 +657,7 @@ async fn main() -> Result<()> {
             .ok_or_else(|| anyhow::anyhow!("Interactive mode requires an input file"))?;
         let records = read_records(input, &cli.format)?;
         let cfg = config::Config::load().await?;
-        let auto_preset = cfg.detect_preset(&records, 50);
+        let auto_preset = cfg.detect_preset(&records, sampling);
         return interactive::run_interactive(records, auto_preset);
     }
 
@@ -529,7 +665,15 @@ async fn main() -> Result<()> {
     let command = match cli.command {
         Some(cmd) => cmd,
         None => {
-            return auto_detect_mode(cli.input.as_ref(), cli.raw, cli.no_gloss, cli.align).await;
+            return auto_detect_mode(
+                cli.input.as_ref(),
+                cli.raw,
+                cli.no_gloss,
+                cli.align,
+                cli.output,
+                jobs,
+            )
+            .await;
         }
     };
 
@@ -598,6 +742,15 @@ async fn main() -> Result<()> {
             let records = read_records(&input, &format)?;
             commands::compare_groups(&records, position, max_positions);
         }
+        Command::Xorkey {
+            input,
+            format,
+            max_len,
+            plaintext,
+        } => {
+            let records = read_records(&input, &format)?;
+            commands::xor_key_analysis(&records, max_len, &plaintext)?;
+        }
         Command::Split {
             input,
             format,
@@ -627,7 +780,7 @@ async fn main() -> Result<()> {
         Command::Interactive { input, format } => {
             let records = read_records(&input, &format)?;
             let cfg = config::Config::load().await?;
-            let auto_preset = cfg.detect_preset(&records, 50);
+            let auto_preset = cfg.detect_preset(&records, sampling);
             interactive::run_interactive(records, auto_preset)?;
         }
         Command::Gloss {
@@ -636,11 +789,44 @@ async fn main() -> Result<()> {
             transform,
             command,
         } => {
-            gloss_command(&input, preset_name, transform, command, cli.raw).await?;
+            gloss_command(
+                &input,
+                preset_name,
+                transform,
+                command,
+                cli.raw,
+                cli.output,
+                jobs,
+            )
+            .await?;
+        }
+        Command::Encode {
+            input,
+            preset,
+            transform,
+            charset,
+            check,
+        } => {
+            commands::encode(&input, preset.as_deref(), transform, charset, check)?;
+        }
+        Command::Detect { input, explain } => {
+            commands::detect(&input, explain)?;
         }
         Command::Presets => {
             list_presets()?;
         }
+        Command::Preset { action } => match action {
+            PresetAction::Test { preset, fixtures } => {
+                commands::preset_test(&preset, &fixtures).await?;
+            }
+            PresetAction::Lint { preset } => {
+                lint::preset_lint(preset.as_deref())?;
+            }
+        },
+        Command::Cache { action } => match action {
+            CacheAction::Clear => cache::clear()?,
+            CacheAction::Stats => cache::stats()?,
+        },
     }
 
     Ok(())
@@ -676,6 +862,8 @@ async fn auto_detect_mode(
     raw: bool,
     no_gloss: bool,
     align: bool,
+    output: Option<output::OutputFormat>,
+    jobs: usize,
 ) -> Result<()> {
     use std::io::{self, BufRead};
 
@@ -686,7 +874,7 @@ async fn auto_detect_mode(
     let presets: Vec<_> = mgr
         .list()
         .iter()
-        .filter_map(|name| mgr.get(name).map(|p| (name.to_string(), p.clone())))
+        .filter_map(|&name| mgr.compiled(name).map(|p| (name, p)))
         .collect();
 
     if presets.is_empty() {
@@ -701,85 +889,116 @@ async fn auto_detect_mode(
 
     // Collect rows for alignment mode
     let mut rows: Vec<Vec<String>> = Vec::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            if align {
-                rows.push(vec![]);
-            } else {
-                println!();
+    let mut writer = output.map(|format| output::RowWriter::new(format, io::stdout()));
+
+    // Results come back in input order, so each is printed where its line was
+    let mut emit =
+        |(line_no, record, matched): (usize, String, Option<(&str, &preset::CompiledPreset)>),
+         result: Option<Result<String>>|
+         -> Result<()> {
+            if let Some(writer) = &mut writer {
+                if !record.is_empty() {
+                    writer.write(output_row(record, matched, result, raw))?;
+                }
+                return Ok(());
+            }
+            if record.is_empty() {
+                if align {
+                    rows.push(vec![]);
+                } else {
+                    println!();
+                }
+                return Ok(());
             }
-            continue;
-        }
 
-        // Find matching preset
-        let record_bytes = trimmed.as_bytes().to_vec();
-        let matching_preset = presets.iter().find(|(_, p)| {
-            !p.detect.is_empty() && p.detect.iter().all(|rule| rule.matches(&record_bytes))
-        });
-
-        match matching_preset {
-            Some((name, preset)) => {
-                if let Some(ref gloss) = preset.gloss {
-                    match gloss.apply(trimmed).await {
-                        Ok(result) => {
-                            if no_gloss {
-                                if align {
-                                    rows.push(vec![name.clone(), trimmed.to_string()]);
-                                } else {
-                                    println!("[{}] {}", name, trimmed);
-                                }
-                            } else if raw {
-                                if align {
-                                    rows.push(vec![result.clone()]);
-                                } else {
-                                    println!("{}", result);
-                                }
-                            } else {
-                                // Extract fields
-                                let gloss_fields: Vec<_> =
-                                    preset.fields.iter().filter(|f| f.from_gloss).collect();
-                                if gloss_fields.is_empty() {
-                                    if align {
-                                        rows.push(vec![result.clone()]);
-                                    } else {
-                                        println!("{}", result);
-                                    }
-                                } else {
-                                    let values = extract_field_values(&result, &gloss_fields);
-                                    if align {
-                                        rows.push(values);
-                                    } else {
-                                        println!("{}", values.join("\t"));
-                                    }
-                                }
-                            }
+            match (matched, result) {
+                (Some((_, preset)), Some(Ok(result))) => {
+                    if raw {
+                        if align {
+                            rows.push(vec![result]);
+                        } else {
+                            println!("{}", result);
                         }
-                        Err(e) => {
-                            if align {
-                                rows.push(vec![format!("[{}] Error: {}", name, e)]);
-                            } else {
-                                eprintln!("[{}] Error: {}", name, e);
-                            }
+                    } else if !preset.extracts_fields() {
+                        // No fields to extract
+                        if align {
+                            rows.push(vec![result]);
+                        } else {
+                            println!("{}", result);
+                        }
+                    } else {
+                        let values = column_values(preset.columns(&record, Some(&result)));
+                        if align {
+                            rows.push(values);
+                        } else {
+                            println!("{}", values.join("\t"));
                         }
                     }
-                } else if align {
-                    rows.push(vec![name.clone(), trimmed.to_string()]);
-                } else {
-                    println!("[{}] {}", name, trimmed);
                 }
-            }
-            None => {
-                if align {
-                    rows.push(vec![trimmed.to_string()]);
-                } else {
-                    println!("{}", trimmed);
+                (Some((name, _)), Some(Err(e))) => {
+                    if align {
+                        rows.push(vec![format!("[{}] Error on line {}: {}", name, line_no, e)]);
+                    } else {
+                        eprintln!("[{}] Error on line {}: {}", name, line_no, e);
+                    }
+                }
+                // Preset without a gloss, or --no-gloss: raw-side fields only
+                (Some((name, preset)), None) => {
+                    let values = column_values(preset.columns(&record, None));
+                    if align {
+                        let mut row = vec![name.to_string(), record];
+                        row.extend(values);
+                        rows.push(row);
+                    } else if values.is_empty() {
+                        println!("[{}] {}", name, record);
+                    } else {
+                        println!("[{}] {}\t{}", name, record, values.join("\t"));
+                    }
+                }
+                (None, _) => {
+                    if align {
+                        rows.push(vec![record]);
+                    } else {
+                        println!("{}", record);
+                    }
                 }
             }
+            Ok(())
+        };
+
+    let mut queue = preset::GlossQueue::new(jobs);
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let trimmed = line.trim().to_string();
+
+        // Find matching preset
+        let matched = if trimmed.is_empty() {
+            None
+        } else {
+            detect::best(&presets, trimmed.as_bytes())
+        };
+        let job = match matched {
+            Some((_, preset)) if !no_gloss => {
+                (preset.gloss.clone()).map(|gloss| (gloss, trimmed.clone()))
+            }
+            _ => None,
+        };
+
+        if let Some((item, results)) = queue.push((idx + 1, trimmed, matched), job).await? {
+            for (item, result) in output_rows(item, results) {
+                emit(item, result)?;
+            }
         }
     }
+    while let Some((item, results)) = queue.pop().await? {
+        for (item, result) in output_rows(item, results) {
+            emit(item, result)?;
+        }
+    }
+
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
 
     // Print aligned table
     if align && !rows.is_empty() {
@@ -789,21 +1008,6 @@ async fn auto_detect_mode(
     Ok(())
 }
 
-/// Extract field values from gloss output
-fn extract_field_values(gloss_output: &str, fields: &[&preset::FieldExtractor]) -> Vec<String> {
-    fields
-        .iter()
-        .map(|field| {
-            regex::Regex::new(&field.pattern)
-                .ok()
-                .and_then(|re| re.captures(gloss_output))
-                .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
-                .map(|m| m.as_str().to_string())
-                .unwrap_or_default()
-        })
-        .collect()
-}
-
 /// Print rows as aligned table
 fn print_aligned_table(rows: &[Vec<String>]) {
     // Find max width for each column
@@ -844,6 +1048,8 @@ async fn gloss_command(
     transform: Option<String>,
     command: Option<String>,
     raw: bool,
+    output: Option<output::OutputFormat>,
+    jobs: usize,
 ) -> Result<()> {
     use std::io::{self, BufRead};
 
@@ -851,30 +1057,22 @@ async fn gloss_command(
     let preset = if let Some(ref name) = preset_name {
         let mut mgr = preset::PresetManager::new();
         mgr.load_all()?;
-        mgr.get(name).cloned()
+        mgr.compiled(name).cloned()
     } else {
         None
     };
 
     // Build gloss config
     let gloss = if let Some(cmd) = command {
-        preset::GlossConfig {
-            transform: None,
-            base85_charset: None,
+        Arc::new(preset::CompiledGloss::new(preset::GlossConfig {
             command: Some(cmd.split_whitespace().map(String::from).collect()),
-            segment: None,
-            fallback: None,
-            cache: true,
-        }
+            ..Default::default()
+        })?)
     } else if let Some(t) = transform {
-        preset::GlossConfig {
+        Arc::new(preset::CompiledGloss::new(preset::GlossConfig {
             transform: Some(t),
-            base85_charset: None,
-            command: None,
-            segment: None,
-            fallback: None,
-            cache: true,
-        }
+            ..Default::default()
+        })?)
     } else if let Some(ref p) = preset {
         p.gloss
             .clone()
@@ -883,11 +1081,8 @@ async fn gloss_command(
         anyhow::bail!("Must specify --preset, --transform, or --command");
     };
 
-    // Get field extractors for gloss output
-    let gloss_fields: Vec<_> = preset
-        .as_ref()
-        .map(|p| p.fields.iter().filter(|f| f.from_gloss).collect())
-        .unwrap_or_default();
+    // Field extraction applies when the preset declares any fields
+    let extract = preset.as_ref().is_some_and(|p| p.extracts_fields());
 
     // Read input lines
     let reader: Box<dyn BufRead> = if input.to_string_lossy() == "-" {
@@ -896,51 +1091,101 @@ async fn gloss_command(
         Box::new(io::BufReader::new(File::open(input)?))
     };
 
-    for line in reader.lines() {
-        let line = line?;
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            println!();
-            continue;
-        }
+    let mut writer = output.map(|format| output::RowWriter::new(format, io::stdout()));
+    let matched = preset.as_ref().map(|p| (p.preset.preset.name.as_str(), p));
 
-        match gloss.apply(trimmed).await {
-            Ok(result) => {
-                if raw || gloss_fields.is_empty() {
-                    // Raw mode or no field extraction - print full output
-                    println!("{}", result);
-                } else {
+    // Results come back in input order, so each is printed where its line was
+    let mut emit = |(line_no, record): (usize, String), result: Option<Result<String>>| {
+        if let Some(writer) = &mut writer {
+            if !record.is_empty() {
+                writer.write(output_row(record, matched, result, raw))?;
+            }
+            return Ok::<_, anyhow::Error>(());
+        }
+        match result {
+            Some(Ok(result)) => {
+                let output = match &preset {
                     // Extract and display fields
-                    print_extracted_fields(trimmed, &result, &gloss_fields);
-                }
+                    Some(p) if extract && !raw => {
+                        column_values(p.columns(&record, Some(&result))).join("\t")
+                    }
+                    // Raw mode or no field extraction - print full output
+                    _ => result,
+                };
+                println!("{}", output);
+            }
+            Some(Err(e)) => eprintln!("# Error on line {}: {}", line_no, e),
+            // Blank line
+            None => println!(),
+        }
+        Ok(())
+    };
+
+    let mut queue = preset::GlossQueue::new(jobs);
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let trimmed = line.trim().to_string();
+        let job = (!trimmed.is_empty()).then(|| (gloss.clone(), trimmed.clone()));
+        if let Some((item, results)) = queue.push((idx + 1, trimmed), job).await? {
+            for (item, result) in output_rows(item, results) {
+                emit(item, result)?;
             }
-            Err(e) => eprintln!("# Error: {}", e),
+        }
+    }
+    while let Some((item, results)) = queue.pop().await? {
+        for (item, result) in output_rows(item, results) {
+            emit(item, result)?;
         }
     }
 
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
     Ok(())
 }
 
-/// Extract and print fields from gloss output as TSV (one line per record)
-fn print_extracted_fields(_input: &str, gloss_output: &str, fields: &[&preset::FieldExtractor]) {
-    let mut values: Vec<String> = Vec::new();
-
-    // Extract each field from the gloss output
-    for field in fields {
-        if let Ok(re) = regex::Regex::new(&field.pattern) {
-            let value = re
-                .captures(gloss_output)
-                .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
-                .map(|m| m.as_str().to_string())
-                .unwrap_or_default();
-            values.push(value);
-        } else {
-            values.push(String::new());
+/// One `(item, result)` per output row of a glossed record; with
+/// `segments = "rows"` a record can give several
+fn output_rows<T: Clone>(
+    item: T,
+    results: Option<preset::Glossed>,
+) -> Vec<(T, Option<Result<String>>)> {
+    match results {
+        Some(results) => (results.into_iter())
+            .map(|result| (item.clone(), Some(result)))
+            .collect(),
+        None => vec![(item, None)],
+    }
+}
+
+/// A record's result as an `--output` row. The whole gloss output is one
+/// `gloss` column unless the preset extracts fields from it.
+fn output_row(
+    record: String,
+    matched: Option<(&str, &preset::CompiledPreset)>,
+    result: Option<Result<String>>,
+    raw: bool,
+) -> output::Row {
+    let mut row = output::Row {
+        record,
+        preset: matched.map(|(name, _)| name.to_string()),
+        ..output::Row::default()
+    };
+    match (result, matched) {
+        (Some(Ok(result)), Some((_, p))) if !raw && p.extracts_fields() => {
+            row.columns = p.columns(&row.record, Some(&result));
         }
+        (Some(Ok(result)), _) => row.columns = vec![("gloss".to_string(), result)],
+        (Some(Err(e)), _) => row.error = Some(e.to_string()),
+        (None, Some((_, p))) => row.columns = p.columns(&row.record, None),
+        (None, None) => {}
     }
+    row
+}
 
-    // Output as tab-separated line
-    println!("{}", values.join("\t"));
+/// Column values without their names, for TSV and `--align`
+fn column_values(columns: Vec<(String, String)>) -> Vec<String> {
+    columns.into_iter().map(|(_, value)| value).collect()
 }
 
 fn split_by_header(records: &[Vec<u8>], header_len: usize, output_dir: &PathBuf) -> Result<()> {
@@ -955,7 +1200,7 @@ fn split_by_header(records: &[Vec<u8>], header_len: usize, output_dir: &PathBuf)
 
     // Sort groups by size (largest first) and assign letters
     let mut sorted_groups: Vec<_> = groups.into_iter().collect();
-    sorted_groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()));
+    sorted_groups.sort_by_key(|g| std::cmp::Reverse(g.1.len()));
 
     println!(
         "Split {} records into {} groups by {}-byte header:\n",
@@ -1045,7 +1290,7 @@ fn frequency_analysis(records: &[Vec<u8>], max_positions: usize, threshold: usiz
 
         // Visual frequency bar
         let bar_len = top_pct * 40 / 100;
-        let bar: String = "█".repeat(bar_len) + &"░".repeat(40 - bar_len);
+        let bar: String = "█".repeat(bar_len) + "░".repeat(40 - bar_len).as_str();
 
         // Mark high-frequency positions
         let marker = if top_pct >= threshold {
@@ -0,0 +1,181 @@
+//! Structured output for gloss and auto-detect modes (`--output`)
+//!
+//! Each record becomes a row with the input record, the matched preset and
+//! one column per extracted field. JSONL is written as records arrive; CSV
+//! and TSV are written at the end, since the header row has to list the
+//! columns of every preset that matched.
+
+use serde_json::{Map, Value};
+use std::io::{self, Write};
+
+/// Format selected with `--output`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// One JSON object per record
+    Jsonl,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row
+    Tsv,
+}
+
+/// One record's results
+#[derive(Debug, Clone, Default)]
+pub struct Row {
+    pub record: String,
+    /// Name of the matched preset, if any
+    pub preset: Option<String>,
+    /// Field names and values, in display order
+    pub columns: Vec<(String, String)>,
+    /// Why the record couldn't be glossed
+    pub error: Option<String>,
+}
+
+/// Writes rows in one of the structured formats
+pub struct RowWriter<W: Write> {
+    format: OutputFormat,
+    out: W,
+    /// Rows held back until the CSV/TSV header is known
+    pending: Vec<Row>,
+}
+
+impl<W: Write> RowWriter<W> {
+    pub fn new(format: OutputFormat, out: W) -> Self {
+        Self {
+            format,
+            out,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, row: Row) -> io::Result<()> {
+        if self.format != OutputFormat::Jsonl {
+            self.pending.push(row);
+            return Ok(());
+        }
+        let mut object = Map::new();
+        object.insert("record".into(), Value::String(row.record));
+        object.insert(
+            "preset".into(),
+            row.preset.map_or(Value::Null, Value::String),
+        );
+        for (name, value) in row.columns {
+            object.insert(name, Value::String(value));
+        }
+        if let Some(error) = row.error {
+            object.insert("error".into(), Value::String(error));
+        }
+        writeln!(self.out, "{}", Value::Object(object))
+    }
+
+    /// Write any held-back rows under a header naming every column seen
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.format != OutputFormat::Jsonl {
+            let mut header = vec!["record".to_string(), "preset".to_string()];
+            for row in &self.pending {
+                for (name, _) in &row.columns {
+                    if !header.contains(name) {
+                        header.push(name.clone());
+                    }
+                }
+            }
+            header.push("error".to_string());
+            self.write_line(&header)?;
+
+            for row in std::mem::take(&mut self.pending) {
+                let mut line = vec![row.record, row.preset.unwrap_or_default()];
+                for name in &header[2..header.len() - 1] {
+                    let value = row.columns.iter().find(|(n, _)| n == name);
+                    line.push(value.map(|(_, v)| v.clone()).unwrap_or_default());
+                }
+                line.push(row.error.unwrap_or_default());
+                self.write_line(&line)?;
+            }
+        }
+        self.out.flush()
+    }
+
+    fn write_line(&mut self, cells: &[String]) -> io::Result<()> {
+        let (separator, escape): (&str, fn(&str) -> String) = match self.format {
+            OutputFormat::Csv => (",", csv_cell),
+            _ => ("\t", tsv_cell),
+        };
+        let cells: Vec<String> = cells.iter().map(|cell| escape(cell)).collect();
+        writeln!(self.out, "{}", cells.join(separator))
+    }
+}
+
+/// Quote a cell if it contains a comma, quote or line break (RFC 4180)
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Backslash-escape tabs, line breaks and backslashes so each row stays one line
+fn tsv_cell(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: OutputFormat, rows: Vec<Row>) -> String {
+        let mut out = Vec::new();
+        let mut writer = RowWriter::new(format, &mut out);
+        for row in rows {
+            writer.write(row).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_row_writer() {
+        let rows = vec![
+            Row {
+                record: "a,1".into(),
+                preset: Some("items".into()),
+                columns: vec![("rarity".into(), "Epic \"gold\"".into())],
+                error: None,
+            },
+            Row {
+                record: "b\t2".into(),
+                preset: Some("jwt".into()),
+                columns: vec![("sub".into(), "alice".into())],
+                error: Some("bad".into()),
+            },
+            Row {
+                record: "c".into(),
+                ..Row::default()
+            },
+        ];
+
+        assert_eq!(
+            render(OutputFormat::Csv, rows.clone()),
+            "record,preset,rarity,sub,error\n\
+             \"a,1\",items,\"Epic \"\"gold\"\"\",,\n\
+             b\t2,jwt,,alice,bad\n\
+             c,,,,\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Tsv, rows.clone()),
+            "record\tpreset\trarity\tsub\terror\n\
+             a,1\titems\tEpic \"gold\"\t\t\n\
+             b\\t2\tjwt\t\talice\tbad\n\
+             c\t\t\t\t\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Jsonl, rows),
+            "{\"record\":\"a,1\",\"preset\":\"items\",\"rarity\":\"Epic \\\"gold\\\"\"}\n\
+             {\"record\":\"b\\t2\",\"preset\":\"jwt\",\"sub\":\"alice\",\"error\":\"bad\"}\n\
+             {\"record\":\"c\",\"preset\":null}\n"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! WebAssembly plugin transforms
+//!
+//! A plugin is a `.wasm` module run by an embedded interpreter. It is given no
+//! imports, so it has no filesystem, network or clock access; its memory is
+//! capped and each call has a fuel budget, so a runaway loop fails the record
+//! instead of hanging. The ABI is bytes in, bytes out:
+//!
+//! - `memory`: the module's exported linear memory
+//! - `alloc(len: i32) -> i32`: returns a buffer of `len` bytes for the input
+//! - `transform(ptr: i32, len: i32) -> i64`: decodes the input, returning the
+//!   output's pointer in the high 32 bits and its length in the low 32 bits
+//!
+//! A trap (e.g. `unreachable`) fails the record. UTF-8 output is shown as text,
+//! which may be JSON for `output = "json"`; anything else is binary.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Instructions (roughly) a single call may run
+const FUEL: u64 = 100_000_000;
+
+/// Largest linear memory a plugin may grow to
+const MAX_MEMORY: usize = 256 << 20;
+
+/// A compiled plugin module
+#[derive(Debug)]
+pub struct Plugin {
+    name: String,
+    module: Module,
+}
+
+/// The interpreter shared by every plugin, with fuel metering on
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Engine::new(&config)
+    })
+}
+
+/// Load and compile a plugin, reusing an earlier load of the same file
+pub fn load(path: &Path) -> Result<Arc<Plugin>> {
+    static LOADED: OnceLock<Mutex<HashMap<PathBuf, Arc<Plugin>>>> = OnceLock::new();
+    let loaded = LOADED.get_or_init(Default::default);
+    if let Some(plugin) = loaded.lock().unwrap().get(path) {
+        return Ok(plugin.clone());
+    }
+    let wasm = std::fs::read(path).with_context(|| format!("failed to read plugin {:?}", path))?;
+    let module = Module::new(engine(), &wasm[..])
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .with_context(|| format!("invalid plugin {:?}", path))?;
+    let name = (path.file_name()).map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into(),
+    );
+    let plugin = Arc::new(Plugin { name, module });
+    (loaded.lock().unwrap()).insert(path.to_path_buf(), plugin.clone());
+    Ok(plugin)
+}
+
+impl Plugin {
+    /// Run `transform` on the input in a fresh instance
+    pub fn call(&self, input: &[u8]) -> Result<Vec<u8>> {
+        self.run(input)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("plugin {}", self.name))
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(engine(), limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(FUEL).map_err(wasmi::Error::from)?;
+
+        let instance = Linker::<StoreLimits>::new(engine())
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = (instance.get_memory(&store, "memory")).ok_or("no exported memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+        let transform = instance.get_typed_func::<(i32, i32), i64>(&store, "transform")?;
+
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        (memory.write(&mut store, ptr as u32 as usize, input)).map_err(wasmi::Error::from)?;
+        let packed = transform.call(&mut store, (ptr, len))? as u64;
+
+        // Check the output lies in memory before copying it out
+        let (start, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        let end = start.saturating_add(len);
+        let output = (memory.data(&store).get(start..end))
+            .ok_or_else(|| format!("output {}..{} is outside memory", start, end))?;
+        Ok(output.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Upper-cases ASCII in place; traps on a leading '!', spins on '?' and
+    /// claims 4 GiB of output on '#'
+    const UPPER: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param $len i32) (result i32) (i32.const 16))
+  (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (local $i i32) (local $b i32)
+    (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 33)) (then unreachable))
+    (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 63)) (then (loop $spin (br $spin))))
+    (if (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 35)) (then (return (i64.const 0xffffffff))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+        (local.set $b (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+        (if (i32.and (i32.ge_u (local.get $b) (i32.const 97)) (i32.le_u (local.get $b) (i32.const 122)))
+          (then (i32.store8 (i32.add (local.get $ptr) (local.get $i)) (i32.sub (local.get $b) (i32.const 32)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (i64.or (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32)) (i64.extend_i32_u (local.get $len)))))
+"#;
+
+    #[test]
+    fn test_plugin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("upper.wasm");
+        std::fs::write(&path, wat::parse_str(UPPER).unwrap()).unwrap();
+
+        let plugin = load(&path).unwrap();
+        assert_eq!(plugin.call(b"hi there").unwrap(), b"HI THERE");
+        assert!(plugin.call(b"!").is_err());
+        assert!(plugin.call(b"?").is_err());
+        let err = plugin.call(b"#").unwrap_err();
+        assert!(format!("{:#}", err).contains("outside memory"));
+
+        std::fs::write(dir.path().join("bad.wasm"), b"nope").unwrap();
+        assert!(load(&dir.path().join("bad.wasm")).is_err());
+        assert!(load(&dir.path().join("missing.wasm")).is_err());
+    }
+}
@@ -48,15 +48,32 @@
 //! name = "rarity"
 //! from_gloss = true
 //! pattern = "Rarity: (\\w+)"
+//!
+//! # Typed fields at fixed offsets in the decoded bytes
+//! [[layout]]
+//! name = "owner"
+//! offset = 4
+//! type = "utf16le:u8len"
 //! ```
 
+use crate::cache::{self, GlossCache};
+use crate::coprocess::{CoProcess, Framing};
+use crate::datatype::DataType;
+use crate::plugin::{self, Plugin};
+use crate::script::{self, Script};
 use anyhow::{Context, Result};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::cell::OnceCell;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use tokio::process::Command;
+use tokio::task::JoinHandle;
 
 /// A complete preset definition
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,6 +90,21 @@ pub struct Preset {
     pub color: Vec<ColorRule>,
     #[serde(default)]
     pub fields: Vec<FieldExtractor>,
+    #[serde(default)]
+    pub layout: Vec<LayoutField>,
+    /// Fixture tests run by `lw preset test`
+    #[serde(default, rename = "test")]
+    pub tests: Vec<PresetTest>,
+}
+
+impl Preset {
+    /// Decode every layout field from the record's decoded bytes
+    pub fn decode_layout(&self, bytes: &[u8]) -> Vec<String> {
+        self.layout
+            .iter()
+            .map(|field| field.decode(bytes))
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -80,6 +112,9 @@ pub struct PresetMeta {
     pub name: String,
     #[serde(default)]
     pub description: String,
+    /// Parent preset to inherit detect, gloss, color, fields and layout from
+    #[serde(default)]
+    pub extends: Option<String>,
 }
 
 /// How records are delimited in the input
@@ -101,17 +136,67 @@ pub enum RecordFormat {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum DetectRule {
-    StartsWith { value: String },
-    EndsWith { value: String },
-    Contains { value: String },
-    Regex { pattern: String },
-    MinLength { value: usize },
-    MaxLength { value: usize },
-    ByteEquals { position: usize, value: u8 },
+    StartsWith {
+        value: String,
+    },
+    EndsWith {
+        value: String,
+    },
+    Contains {
+        value: String,
+    },
+    Regex {
+        pattern: String,
+    },
+    MinLength {
+        value: usize,
+    },
+    MaxLength {
+        value: usize,
+    },
+    LengthEquals {
+        value: usize,
+    },
+    /// Record length is `remainder` modulo `modulus`
+    LengthModulo {
+        modulus: usize,
+        #[serde(default)]
+        remainder: usize,
+    },
+    ByteEquals {
+        position: usize,
+        value: u8,
+    },
+    /// Hex byte pattern with `??` wildcards, at `offset` or anywhere if unset
+    Bytes {
+        pattern: String,
+        #[serde(default)]
+        offset: Option<usize>,
+    },
+    /// At least one of the nested rules matches
+    Any {
+        rules: Vec<DetectRule>,
+    },
+    /// Every nested rule matches
+    All {
+        rules: Vec<DetectRule>,
+    },
+    /// The nested rule does not match
+    Not {
+        rule: Box<DetectRule>,
+    },
+    /// A Rhai script returning true, with `record` (text) and `bytes` (blob) in scope
+    Script {
+        script: String,
+        /// How much a match says about the record (default: 1)
+        #[serde(default)]
+        bits: Option<f64>,
+    },
 }
 
 impl DetectRule {
-    /// Check if a record matches this rule
+    /// Check if a record matches this rule; rules with regexes or byte patterns
+    /// are compiled on each call, so use `CompiledPreset` for repeated matching
     pub fn matches(&self, record: &[u8]) -> bool {
         match self {
             Self::StartsWith { value } => {
@@ -126,16 +211,224 @@ impl DetectRule {
                 let s = String::from_utf8_lossy(record);
                 s.contains(value)
             }
-            Self::Regex { pattern } => {
-                // TODO: compile regex once
-                let s = String::from_utf8_lossy(record);
-                regex::Regex::new(pattern)
-                    .map(|re| re.is_match(&s))
-                    .unwrap_or(false)
-            }
             Self::MinLength { value } => record.len() >= *value,
             Self::MaxLength { value } => record.len() <= *value,
+            Self::LengthEquals { value } => record.len() == *value,
+            Self::LengthModulo { modulus, remainder } => {
+                *modulus != 0 && record.len() % modulus == *remainder
+            }
             Self::ByteEquals { position, value } => record.get(*position) == Some(value),
+            _ => self.compile().is_ok_and(|m| m.matches(record)),
+        }
+    }
+
+    /// Compile the rule's regexes and byte patterns
+    fn compile(&self) -> Result<Matcher> {
+        Ok(match self {
+            Self::Regex { pattern } => Matcher::Regex(
+                Regex::new(pattern)
+                    .with_context(|| format!("invalid detect pattern {:?}", pattern))?,
+            ),
+            Self::Bytes { pattern, offset } => Matcher::Bytes {
+                pattern: parse_byte_pattern(pattern)
+                    .map_err(|e| anyhow::anyhow!("invalid byte pattern {:?}: {}", pattern, e))?,
+                offset: *offset,
+            },
+            Self::LengthModulo { modulus: 0, .. } => {
+                anyhow::bail!("length_modulo modulus must be positive")
+            }
+            Self::Any { rules } => {
+                Matcher::Any(rules.iter().map(Self::compile).collect::<Result<_>>()?)
+            }
+            Self::All { rules } => {
+                Matcher::All(rules.iter().map(Self::compile).collect::<Result<_>>()?)
+            }
+            Self::Not { rule } => Matcher::Not(Box::new(rule.compile()?)),
+            Self::Script { script, .. } => {
+                Matcher::Script(script::compile(script).context("invalid detect script")?)
+            }
+            _ => Matcher::Simple(self.clone()),
+        })
+    }
+
+    /// How much a match says about the record, in bits: literal bytes count 8,
+    /// length bounds 1, regexes the information in their shortest possible match
+    fn specificity(&self) -> f64 {
+        match self {
+            Self::StartsWith { value } | Self::EndsWith { value } => 8.0 * value.len() as f64,
+            // Unanchored, so each byte says less
+            Self::Contains { value } => 4.0 * value.len() as f64,
+            Self::Regex { pattern } => regex_syntax::Parser::new()
+                .parse(pattern)
+                .map(|hir| hir_bits(&hir).max(1.0))
+                .unwrap_or(0.0),
+            Self::MinLength { .. } | Self::MaxLength { .. } => 1.0,
+            Self::LengthEquals { .. } => 4.0,
+            Self::LengthModulo { modulus, .. } => (*modulus.max(&1) as f64).log2(),
+            Self::ByteEquals { .. } => 8.0,
+            Self::Bytes { pattern, offset } => {
+                let fixed = parse_byte_pattern(pattern)
+                    .map(|p| p.iter().flatten().count())
+                    .unwrap_or(0);
+                let per_byte = if offset.is_some() { 8.0 } else { 4.0 };
+                per_byte * fixed as f64
+            }
+            // Only as strong as the weakest alternative
+            Self::Any { rules } => rules
+                .iter()
+                .map(Self::specificity)
+                .reduce(f64::min)
+                .unwrap_or(0.0),
+            Self::All { rules } => rules.iter().map(Self::specificity).sum(),
+            Self::Not { .. } => 1.0,
+            // Opaque, so only as specific as the preset says
+            Self::Script { bits, .. } => bits.unwrap_or(1.0),
+        }
+    }
+
+    /// Short description for `lw detect --explain`
+    pub fn describe(&self) -> String {
+        let list = |rules: &[DetectRule]| {
+            rules
+                .iter()
+                .map(Self::describe)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::StartsWith { value } => format!("starts_with {:?}", value),
+            Self::EndsWith { value } => format!("ends_with {:?}", value),
+            Self::Contains { value } => format!("contains {:?}", value),
+            Self::Regex { pattern } => format!("regex {:?}", pattern),
+            Self::MinLength { value } => format!("min_length {}", value),
+            Self::MaxLength { value } => format!("max_length {}", value),
+            Self::LengthEquals { value } => format!("length_equals {}", value),
+            Self::LengthModulo { modulus, remainder } => {
+                format!("length_modulo {} = {}", modulus, remainder)
+            }
+            Self::ByteEquals { position, value } => {
+                format!("byte_equals [{}] = 0x{:02x}", position, value)
+            }
+            Self::Bytes {
+                pattern,
+                offset: Some(offset),
+            } => format!("bytes [{}] {:?}", offset, pattern),
+            Self::Bytes { pattern, .. } => format!("bytes {:?}", pattern),
+            Self::Any { rules } => format!("any({})", list(rules)),
+            Self::All { rules } => format!("all({})", list(rules)),
+            Self::Not { rule } => format!("not({})", rule.describe()),
+            Self::Script { script, .. } => format!("script {:?}", script),
+        }
+    }
+}
+
+/// A detect rule with its regexes and byte patterns compiled
+#[derive(Debug, Clone)]
+enum Matcher {
+    /// Rules that need no compiling
+    Simple(DetectRule),
+    Regex(Regex),
+    Bytes {
+        pattern: Vec<Option<u8>>,
+        offset: Option<usize>,
+    },
+    Any(Vec<Matcher>),
+    All(Vec<Matcher>),
+    Not(Box<Matcher>),
+    Script(Arc<Script>),
+}
+
+impl Matcher {
+    /// Check if a record matches this rule
+    fn matches(&self, record: &[u8]) -> bool {
+        match self {
+            Self::Regex(re) => re.is_match(&String::from_utf8_lossy(record)),
+            Self::Bytes { pattern, offset } => match_byte_pattern(record, pattern, *offset),
+            Self::Any(rules) => rules.iter().any(|rule| rule.matches(record)),
+            Self::All(rules) => rules.iter().all(|rule| rule.matches(record)),
+            Self::Not(rule) => !rule.matches(record),
+            // A script that fails or returns anything but true doesn't match
+            Self::Script(script) => script
+                .eval(vec![
+                    (
+                        "record",
+                        String::from_utf8_lossy(record).into_owned().into(),
+                    ),
+                    ("bytes", rhai::Dynamic::from_blob(record.to_vec())),
+                ])
+                .is_ok_and(|value| value.as_bool() == Ok(true)),
+            Self::Simple(rule) => rule.matches(record),
+        }
+    }
+}
+
+/// Parse a hex byte pattern such as "21 ?? 00 ff" (`??` matches any byte)
+pub fn parse_byte_pattern(pattern: &str) -> Result<Vec<Option<u8>>, String> {
+    let digits: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err("odd number of hex digits".to_string());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| match (pair[0], pair[1]) {
+            ('?', '?') => Ok(None),
+            (hi, lo) => match (hi.to_digit(16), lo.to_digit(16)) {
+                (Some(h), Some(l)) => Ok(Some((h * 16 + l) as u8)),
+                _ => Err(format!("invalid byte '{}{}'", hi, lo)),
+            },
+        })
+        .collect()
+}
+
+/// Match a parsed byte pattern at `offset`, or anywhere in the record if `None`
+pub fn match_byte_pattern(record: &[u8], pattern: &[Option<u8>], offset: Option<usize>) -> bool {
+    let matches_at = |start: usize| {
+        record
+            .get(start..start + pattern.len())
+            .is_some_and(|window| {
+                window
+                    .iter()
+                    .zip(pattern)
+                    .all(|(byte, want)| want.is_none_or(|want| *byte == want))
+            })
+    };
+    match offset {
+        Some(offset) => matches_at(offset),
+        None => (0..=record.len().saturating_sub(pattern.len())).any(matches_at),
+    }
+}
+
+/// Information in the shortest string a regex can match: 8 bits per literal
+/// byte, log2(256 / size) per character class, nothing for anchors
+fn hir_bits(hir: &regex_syntax::hir::Hir) -> f64 {
+    use regex_syntax::hir::{Class, HirKind};
+
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => 0.0,
+        HirKind::Literal(lit) => 8.0 * lit.0.len() as f64,
+        HirKind::Class(class) => {
+            let size = match class {
+                Class::Unicode(c) => c
+                    .ranges()
+                    .iter()
+                    .map(|r| (r.end().min('\u{ff}') as i64 - r.start() as i64 + 1).max(0))
+                    .sum::<i64>(),
+                Class::Bytes(c) => c
+                    .ranges()
+                    .iter()
+                    .map(|r| r.end() as i64 - r.start() as i64 + 1)
+                    .sum::<i64>(),
+            };
+            (256.0 / size.max(1) as f64).log2().max(0.0)
+        }
+        HirKind::Repetition(rep) => rep.min.min(64) as f64 * hir_bits(&rep.sub),
+        HirKind::Capture(cap) => hir_bits(&cap.sub),
+        HirKind::Concat(parts) => parts.iter().map(hir_bits).sum(),
+        HirKind::Alternation(branches) => {
+            branches.iter().map(hir_bits).fold(f64::INFINITY, f64::min)
         }
     }
 }
@@ -153,16 +446,631 @@ pub struct GlossConfig {
     /// External command to run for transformation
     #[serde(default)]
     pub command: Option<Vec<String>>,
+    /// Rhai script run with the record as the blob `data` (see `script.rs`)
+    #[serde(default)]
+    pub transform_script: Option<String>,
+    /// WebAssembly plugin run on the record (see `plugin.rs`), relative to the preset file
+    #[serde(default)]
+    pub wasm: Option<PathBuf>,
     /// Regex pattern to extract segments from input (with capture group)
     /// If set, only the captured segment is passed to the transform/command
     #[serde(default)]
     pub segment: Option<String>,
+    /// Which `segment` matches are glossed when a record has several
+    #[serde(default)]
+    pub segments: SegmentMode,
     /// Fallback transform if command fails: base85, base64, hex, input
     #[serde(default)]
     pub fallback: Option<String>,
     /// Cache transformed results
     #[serde(default = "default_true")]
     pub cache: bool,
+    /// Pipeline run in order instead of `transform`/`command`
+    #[serde(default)]
+    pub steps: Vec<GlossStep>,
+    /// File holding the HMAC secret used to verify signatures with transform = "jwt"
+    #[serde(default)]
+    pub jwt_key: Option<PathBuf>,
+    /// Key for transform = "xor" or "xor_rolling", as hex bytes
+    #[serde(default)]
+    pub xor_key: Option<String>,
+    /// Keep `command` running and stream records to it: "lines" or "json"
+    #[serde(default)]
+    pub server: Option<Framing>,
+    /// Give up on a command after this long per record
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// What the gloss produces: "text" or "json" (one JSON value per record)
+    #[serde(default)]
+    pub output: GlossOutput,
+}
+
+/// An empty config, as an empty `[gloss]` table would parse
+impl Default for GlossConfig {
+    fn default() -> Self {
+        Self {
+            transform: None,
+            base85_charset: None,
+            command: None,
+            transform_script: None,
+            wasm: None,
+            segment: None,
+            segments: SegmentMode::First,
+            fallback: None,
+            cache: true,
+            steps: Vec::new(),
+            jwt_key: None,
+            xor_key: None,
+            server: None,
+            timeout_ms: None,
+            output: GlossOutput::Text,
+        }
+    }
+}
+
+/// Which matches of the gloss `segment` pattern are glossed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentMode {
+    /// Only the first match
+    #[default]
+    First,
+    /// Every match, with its gloss spliced in after it as `segment(gloss)`
+    Inline,
+    /// Every match, each giving its own output row
+    Rows,
+}
+
+/// Kind of gloss output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlossOutput {
+    #[default]
+    Text,
+    /// Must parse as JSON; fields can use `json` paths and columns default to its values
+    Json,
+}
+
+/// One stage of a `[[gloss.steps]]` pipeline: a built-in transform or a command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlossStep {
+    /// Built-in transform (see `BUILTIN_TRANSFORMS`)
+    #[serde(default)]
+    pub transform: Option<String>,
+    /// Base85 variant for this step (defaults to the gloss config's)
+    #[serde(default)]
+    pub base85_charset: Option<String>,
+    /// Command that reads the data on stdin and writes the result to stdout
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// Rhai script run with the previous step's bytes as the blob `data`
+    #[serde(default)]
+    pub transform_script: Option<String>,
+    /// WebAssembly plugin run on the previous step's bytes
+    #[serde(default)]
+    pub wasm: Option<PathBuf>,
+    /// JWT signing key file for this step (defaults to the gloss config's)
+    #[serde(default)]
+    pub jwt_key: Option<PathBuf>,
+    /// XOR key for this step (defaults to the gloss config's)
+    #[serde(default)]
+    pub xor_key: Option<String>,
+}
+
+impl GlossStep {
+    /// Name for error messages: the transform, "script", "wasm" or the command's program
+    pub fn describe(&self) -> String {
+        if let Some(transform) = &self.transform {
+            return transform.clone();
+        }
+        match (&self.transform_script, &self.wasm, &self.command) {
+            (Some(_), _, _) => "script".to_string(),
+            (None, Some(_), _) => "wasm".to_string(),
+            (None, None, Some(cmd)) => cmd.first().cloned().unwrap_or_default(),
+            (None, None, None) => "none".to_string(),
+        }
+    }
+
+    async fn run(&self, data: Payload, defaults: &GlossConfig) -> Result<Payload> {
+        if let Some(transform) = &self.transform {
+            let charset = self
+                .base85_charset
+                .as_ref()
+                .or(defaults.base85_charset.as_ref());
+            let key = self.jwt_key.as_ref().or(defaults.jwt_key.as_ref());
+            let xor_key = self.xor_key.as_ref().or(defaults.xor_key.as_ref());
+            return run_builtin(
+                transform,
+                charset.map(String::as_str),
+                key.map(PathBuf::as_path),
+                xor_key.map(String::as_str),
+                data,
+            );
+        }
+        if let Some(source) = &self.transform_script {
+            return run_script(source, data);
+        }
+        if let Some(path) = &self.wasm {
+            return run_plugin(path, data);
+        }
+        match &self.command {
+            Some(cmd) if !cmd.is_empty() => run_piped(cmd, &data.bytes, defaults.timeout()).await,
+            _ => Ok(data),
+        }
+    }
+}
+
+/// Run a `transform_script` with the data in scope as the blob `data`. A blob
+/// result is binary data; anything else is shown as text (see `script::to_text`).
+fn run_script(source: &str, data: Payload) -> Result<Payload> {
+    let value =
+        script::compile(source)?.eval(vec![("data", rhai::Dynamic::from_blob(data.bytes))])?;
+    Ok(if value.is_blob() {
+        Payload::binary(value.into_blob().unwrap_or_default())
+    } else {
+        Payload::text(&script::to_text(value))
+    })
+}
+
+/// Run a `wasm` plugin on the data. UTF-8 output is text, anything else binary.
+fn run_plugin(path: &Path, data: Payload) -> Result<Payload> {
+    Ok(Payload::output(plugin::load(path)?.call(&data.bytes)?))
+}
+
+/// Data flowing between gloss steps. Decoders produce binary data, which is
+/// only converted to hex for display; text passes through as-is.
+#[derive(Debug, Clone)]
+struct Payload {
+    bytes: Vec<u8>,
+    binary: bool,
+    /// Set when a decompressor hit the end of a truncated stream; `bytes` holds
+    /// what was recovered before it
+    truncated: Option<String>,
+}
+
+impl Payload {
+    fn text(s: &str) -> Self {
+        Self {
+            bytes: s.as_bytes().to_vec(),
+            binary: false,
+            truncated: None,
+        }
+    }
+
+    fn binary(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            binary: true,
+            truncated: None,
+        }
+    }
+
+    /// Command output: UTF-8 is text (without the trailing newline), anything else binary
+    fn output(mut bytes: Vec<u8>) -> Self {
+        match std::str::from_utf8(&bytes) {
+            Ok(s) => {
+                let len = s.trim_end_matches(['\n', '\r']).len();
+                bytes.truncate(len);
+                Self {
+                    bytes,
+                    binary: false,
+                    truncated: None,
+                }
+            }
+            Err(_) => Self::binary(bytes),
+        }
+    }
+
+    /// Decoded bytes that are usually text: UTF-8 is kept as text, anything else binary
+    fn decoded(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => Self {
+                bytes: text.into_bytes(),
+                binary: false,
+                truncated: None,
+            },
+            Err(e) => Self::binary(e.into_bytes()),
+        }
+    }
+
+    /// Ask for the data as text, for decoders that take encoded strings
+    fn as_text(&self) -> Result<&str> {
+        std::str::from_utf8(&self.bytes).context("expected text input")
+    }
+
+    fn display(&self) -> String {
+        let shown = if self.binary {
+            hex::encode(&self.bytes)
+        } else {
+            String::from_utf8_lossy(&self.bytes).into_owned()
+        };
+        match &self.truncated {
+            Some(note) => format!("[truncated: {}] {}", note, shown),
+            None => shown,
+        }
+    }
+}
+
+/// Run a built-in transform on a payload
+fn run_builtin(
+    transform: &str,
+    charset: Option<&str>,
+    jwt_key: Option<&Path>,
+    xor_key: Option<&str>,
+    data: Payload,
+) -> Result<Payload> {
+    match transform {
+        "base85" => {
+            // Use configured charset or default to ASCII85
+            let charset = charset
+                .and_then(base85_charsets::get)
+                .unwrap_or(base85_charsets::ASCII85);
+            let bytes = base85_charsets::decode(data.as_text()?.trim(), charset)
+                .map_err(|e| anyhow::anyhow!("base85 decode error: {}", e))?;
+            Ok(Payload::binary(bytes))
+        }
+        "base64" => {
+            use base64::Engine;
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(data.as_text()?.trim())
+                .context("base64 decode error")?;
+            Ok(Payload::binary(bytes))
+        }
+        "base64url" => {
+            use base64::Engine;
+            let bytes = BASE64_URL
+                .decode(data.as_text()?.trim())
+                .context("base64url decode error")?;
+            Ok(Payload::binary(bytes))
+        }
+        "base32" => {
+            // TOTP secrets are often lowercase, grouped with spaces and unpadded
+            let cleaned = (data.as_text()?.chars())
+                .filter(|c| !c.is_whitespace() && *c != '=')
+                .collect::<String>()
+                .to_uppercase();
+            let bytes = data_encoding::BASE32_NOPAD
+                .decode(cleaned.as_bytes())
+                .context("base32 decode error")?;
+            Ok(Payload::binary(bytes))
+        }
+        "base58" => {
+            let bytes = bs58::decode(data.as_text()?.trim())
+                .into_vec()
+                .context("base58 decode error")?;
+            Ok(Payload::binary(bytes))
+        }
+        "base91" => {
+            let bytes = base91::decode(data.as_text()?.trim())
+                .map_err(|e| anyhow::anyhow!("base91 decode error: {}", e))?;
+            Ok(Payload::binary(bytes))
+        }
+        "url" => {
+            let bytes: Vec<u8> =
+                percent_encoding::percent_decode(data.as_text()?.trim().as_bytes()).collect();
+            Ok(Payload::decoded(bytes))
+        }
+        "quoted_printable" => {
+            let bytes = quoted_printable::decode(
+                data.as_text()?.trim_end_matches(['\n', '\r']),
+                quoted_printable::ParseMode::Robust,
+            )
+            .context("quoted-printable decode error")?;
+            Ok(Payload::decoded(bytes))
+        }
+        "hex" => {
+            // Already hex, just clean it up
+            Ok(Payload::text(
+                &data.as_text()?.replace([' ', '\n', '\r'], ""),
+            ))
+        }
+        "hex_decode" => {
+            let cleaned = data.as_text()?.replace([' ', '\n', '\r', '\t'], "");
+            let bytes = hex::decode(cleaned).context("hex decode error")?;
+            Ok(Payload::binary(bytes))
+        }
+        "zlib" | "gzip" | "deflate" | "lz4" | "zstd" => decompress(transform, &data.bytes),
+        "xor" | "xor_rolling" => Ok(Payload::binary(xor(
+            &data.bytes,
+            &parse_xor_key(xor_key)?,
+            transform == "xor_rolling",
+            false,
+        ))),
+        "jwt" => {
+            let key = match jwt_key {
+                Some(path) => {
+                    let mut key = fs::read(path)
+                        .with_context(|| format!("failed to read JWT key {:?}", path))?;
+                    // Allow the newline editors and `echo` leave at the end of the file
+                    if key.ends_with(b"\n") {
+                        key.pop();
+                        if key.ends_with(b"\r") {
+                            key.pop();
+                        }
+                    }
+                    Some(key)
+                }
+                None => None,
+            };
+            Ok(Payload::text(&decode_jwt(
+                data.as_text()?.trim(),
+                key.as_deref(),
+            )?))
+        }
+        "none" | "" => Ok(data),
+        _ => Err(anyhow::anyhow!("unknown transform: {}", transform)),
+    }
+}
+
+/// Largest output a decompressor may produce for one record, so a small
+/// compressed bomb can't exhaust memory
+const MAX_DECOMPRESSED: u64 = 16 << 20;
+
+/// Decompress a zlib, gzip, raw deflate, LZ4 frame or zstd stream. If the stream
+/// is cut short, whatever decoded before the error is kept and marked truncated.
+fn decompress(format: &str, input: &[u8]) -> Result<Payload> {
+    use std::io::Read;
+
+    let decoder: Box<dyn Read + '_> = match format {
+        "zlib" => Box::new(flate2::read::ZlibDecoder::new(input)),
+        "gzip" => Box::new(flate2::read::MultiGzDecoder::new(input)),
+        "deflate" => Box::new(flate2::read::DeflateDecoder::new(input)),
+        "lz4" => Box::new(lz4_flex::frame::FrameDecoder::new(input)),
+        "zstd" => Box::new(zstd::stream::read::Decoder::new(input)?),
+        _ => anyhow::bail!("unknown compression format: {}", format),
+    };
+
+    // One byte past the limit tells a stream that hits it from one that fills it
+    let mut output = Vec::new();
+    match decoder.take(MAX_DECOMPRESSED + 1).read_to_end(&mut output) {
+        Ok(_) if output.len() as u64 > MAX_DECOMPRESSED => Err(anyhow::anyhow!(
+            "{} output exceeds {} bytes",
+            format,
+            MAX_DECOMPRESSED
+        )),
+        Ok(_) => Ok(Payload::binary(output)),
+        Err(e) if output.is_empty() => Err(anyhow::anyhow!("{} decompress error: {}", format, e)),
+        Err(e) => {
+            let mut payload = Payload::binary(output);
+            payload.truncated = Some(format!(
+                "{} stream ended after {} bytes ({})",
+                format,
+                payload.bytes.len(),
+                e
+            ));
+            Ok(payload)
+        }
+    }
+}
+
+/// An `xor_key`: hex bytes, optionally separated by spaces
+pub fn parse_xor_key(key: Option<&str>) -> Result<Vec<u8>> {
+    let key = key.context("xor needs an xor_key (hex bytes)")?;
+    let key = hex::decode(key.replace(' ', "")).context("xor_key is not hex")?;
+    anyhow::ensure!(!key.is_empty(), "xor_key is empty");
+    Ok(key)
+}
+
+/// XOR data with a repeating key. With `rolling`, only the first pass uses the
+/// key: after that each byte is XORed with the encoded byte one key length
+/// back, so a one-byte key chains every byte to the one before it.
+fn xor(data: &[u8], key: &[u8], rolling: bool, encode: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        let k = match i.checked_sub(key.len()) {
+            Some(back) if rolling => {
+                if encode {
+                    out[back]
+                } else {
+                    data[back]
+                }
+            }
+            _ => key[i % key.len()],
+        };
+        out.push(byte ^ k);
+    }
+    out
+}
+
+/// Transforms that `GlossConfig::encode` can reverse
+pub const ENCODERS: &[&str] = &[
+    "base85",
+    "base64",
+    "base64url",
+    "base32",
+    "base58",
+    "base91",
+    "url",
+    "quoted_printable",
+    "hex",
+    "hex_decode",
+    "xor",
+    "xor_rolling",
+    "none",
+];
+
+/// Reverse a built-in transform: bytes back to the encoded text
+fn encode_builtin(
+    transform: &str,
+    charset: Option<&str>,
+    xor_key: Option<&str>,
+    bytes: Vec<u8>,
+) -> Result<Vec<u8>> {
+    Ok(match transform {
+        "base85" => {
+            let charset = charset
+                .and_then(base85_charsets::get)
+                .unwrap_or(base85_charsets::ASCII85);
+            base85_charsets::encode(&bytes, charset).into_bytes()
+        }
+        "base64" => {
+            use base64::Engine;
+            base64::engine::general_purpose::STANDARD
+                .encode(bytes)
+                .into_bytes()
+        }
+        "base64url" => {
+            use base64::Engine;
+            BASE64_URL.encode(bytes).into_bytes()
+        }
+        "base32" => data_encoding::BASE32_NOPAD.encode(&bytes).into_bytes(),
+        "base58" => bs58::encode(bytes).into_vec(),
+        "base91" => base91::encode(&bytes).into_bytes(),
+        "url" => percent_encoding::percent_encode(&bytes, URL_UNRESERVED)
+            .to_string()
+            .into_bytes(),
+        "quoted_printable" => quoted_printable::encode(bytes),
+        "hex_decode" => hex::encode(bytes).into_bytes(),
+        "xor" | "xor_rolling" => xor(
+            &bytes,
+            &parse_xor_key(xor_key)?,
+            transform == "xor_rolling",
+            true,
+        ),
+        "hex" | "none" | "" => bytes,
+        _ => anyhow::bail!("transform '{}' has no encoder", transform),
+    })
+}
+
+/// URL-safe base64, written without padding. It should be unpadded, but some
+/// encoders pad anyway, so padding is accepted when decoding.
+const BASE64_URL: base64::engine::GeneralPurpose = base64::engine::GeneralPurpose::new(
+    &base64::alphabet::URL_SAFE,
+    base64::engine::general_purpose::NO_PAD
+        .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent),
+);
+
+/// Percent-encoding leaves only RFC 3986 unreserved characters as they are
+const URL_UNRESERVED: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Registered claims listed after the payload; the time claims are rendered as ISO-8601
+const JWT_CLAIMS: &[&str] = &["iss", "sub", "aud", "jti", "iat", "nbf", "exp"];
+const JWT_TIME_CLAIMS: &[&str] = &["iat", "nbf", "exp"];
+
+/// Decode a JWT into its pretty-printed header and payload, one `claim: value`
+/// line per registered claim, and the signature status. HMAC signatures
+/// (HS256/384/512) are checked when a key is given.
+fn decode_jwt(token: &str, key: Option<&[u8]>) -> Result<String> {
+    use base64::Engine;
+    use std::fmt::Write;
+
+    let parts: Vec<&str> = token.split('.').collect();
+    let [header, payload, signature] = parts[..] else {
+        anyhow::bail!("expected 3 dot-separated JWT parts, found {}", parts.len());
+    };
+    let json = |part: &str, what: &str| -> Result<serde_json::Value> {
+        let bytes = BASE64_URL
+            .decode(part)
+            .with_context(|| format!("JWT {} is not URL-safe base64", what))?;
+        serde_json::from_slice(&bytes).with_context(|| format!("JWT {} is not JSON", what))
+    };
+    let header_json = json(header, "header")?;
+    let payload_json = json(payload, "payload")?;
+
+    let mut out = format!(
+        "header: {}\npayload: {}\n",
+        serde_json::to_string_pretty(&header_json)?,
+        serde_json::to_string_pretty(&payload_json)?
+    );
+    for &claim in JWT_CLAIMS {
+        let Some(value) = payload_json.get(claim) else {
+            continue;
+        };
+        let shown = match value {
+            serde_json::Value::Number(n) if JWT_TIME_CLAIMS.contains(&claim) => n
+                .as_i64()
+                .map(|secs| crate::datatype::timestamp::iso8601(secs, 0))
+                .unwrap_or_else(|| n.to_string()),
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        writeln!(out, "{}: {}", claim, shown)?;
+    }
+
+    let alg = header_json["alg"].as_str().unwrap_or("none");
+    let status = match key {
+        None => "unverified",
+        Some(key) => {
+            let signed = format!("{}.{}", header, payload);
+            let signature = BASE64_URL.decode(signature).unwrap_or_default();
+            let verified = match alg {
+                "HS256" => Some(hmac_verify::<sha2::Sha256>(key, &signed, &signature)),
+                "HS384" => Some(hmac_verify::<sha2::Sha384>(key, &signed, &signature)),
+                "HS512" => Some(hmac_verify::<sha2::Sha512>(key, &signed, &signature)),
+                _ => None,
+            };
+            match verified {
+                Some(true) => "valid",
+                Some(false) => "INVALID",
+                None => "unverified, not an HMAC algorithm",
+            }
+        }
+    };
+    write!(out, "signature: {} ({})", status, alg)?;
+    Ok(out)
+}
+
+/// Check an HMAC signature in constant time
+fn hmac_verify<D>(key: &[u8], data: &str, signature: &[u8]) -> bool
+where
+    D: sha2::digest::Digest + sha2::digest::core_api::BlockSizeUser + Clone,
+{
+    use hmac::{Mac, SimpleHmac};
+    let Ok(mut mac) = <SimpleHmac<D> as Mac>::new_from_slice(key) else {
+        return false;
+    };
+    mac.update(data.as_bytes());
+    mac.verify_slice(signature).is_ok()
+}
+
+/// Run a pipeline command with the data on stdin
+async fn run_piped(cmd: &[String], input: &[u8], timeout: Option<Duration>) -> Result<Payload> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut child = Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to run {}", cmd[0]))?;
+
+    // Write from a separate task so a command that fills its stdout pipe can't deadlock us
+    let mut stdin = child.stdin.take().context("command stdin unavailable")?;
+    let input = input.to_vec();
+    let writer = tokio::spawn(async move {
+        // A command that exits without reading its input is not an error
+        let _ = stdin.write_all(&input).await;
+    });
+
+    let output = with_timeout(timeout, &cmd[0], child.wait_with_output()).await??;
+    writer.await.ok();
+
+    if output.status.success() {
+        Ok(Payload::output(output.stdout))
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow::anyhow!("{} failed: {}", cmd[0], stderr.trim()))
+    }
+}
+
+/// Await a command, failing once `timeout` passes (the command is killed on drop)
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    name: &str,
+    future: impl std::future::Future<Output = T>,
+) -> Result<T> {
+    match timeout {
+        Some(limit) => tokio::time::timeout(limit, future)
+            .await
+            .map_err(|_| anyhow::anyhow!("{} timed out after {} ms", name, limit.as_millis())),
+        None => Ok(future.await),
+    }
 }
 
 /// Base85 character sets
@@ -189,6 +1097,28 @@ pub mod base85_charsets {
         }
     }
 
+    /// Encode base85 with custom charset. A final partial group of n bytes is
+    /// zero-padded and written as its first n+1 characters, which is what
+    /// `decode` expects.
+    pub fn encode(input: &[u8], charset: &[u8; 85]) -> String {
+        let mut result = String::with_capacity(input.len().div_ceil(4) * 5);
+
+        for chunk in input.chunks(4) {
+            let mut group = [0u8; 4];
+            group[..chunk.len()].copy_from_slice(chunk);
+            let mut acc = u32::from_be_bytes(group);
+
+            let mut digits = [0u8; 5];
+            for digit in digits.iter_mut().rev() {
+                *digit = charset[(acc % 85) as usize];
+                acc /= 85;
+            }
+            result.extend(digits[..chunk.len() + 1].iter().map(|&c| c as char));
+        }
+
+        result
+    }
+
     /// Decode base85 with custom charset
     pub fn decode(input: &str, charset: &[u8; 85]) -> Result<Vec<u8>, String> {
         // Build reverse lookup table
@@ -239,41 +1169,219 @@ pub mod base85_charsets {
     }
 }
 
+/// basE91: 13 or 14 bits per pair of characters from a 91-character alphabet
+pub mod base91 {
+    const ALPHABET: &[u8; 91] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz\
+        0123456789!#$%&()*+,./:;<=>?@[]^_`{|}~\"";
+
+    pub fn encode(input: &[u8]) -> String {
+        let mut result = String::with_capacity(input.len() * 16 / 13 + 2);
+        let (mut bits, mut count) = (0u32, 0);
+        for &byte in input {
+            bits |= (byte as u32) << count;
+            count += 8;
+            if count > 13 {
+                // Take 13 bits when that still leaves a value above 88, else 14
+                let mut value = bits & 8191;
+                if value > 88 {
+                    bits >>= 13;
+                    count -= 13;
+                } else {
+                    value = bits & 16383;
+                    bits >>= 14;
+                    count -= 14;
+                }
+                result.push(ALPHABET[(value % 91) as usize] as char);
+                result.push(ALPHABET[(value / 91) as usize] as char);
+            }
+        }
+        if count > 0 {
+            result.push(ALPHABET[(bits % 91) as usize] as char);
+            if count > 7 || bits > 90 {
+                result.push(ALPHABET[(bits / 91) as usize] as char);
+            }
+        }
+        result
+    }
+
+    pub fn decode(input: &str) -> Result<Vec<u8>, String> {
+        let mut lookup = [255u8; 256];
+        for (i, &c) in ALPHABET.iter().enumerate() {
+            lookup[c as usize] = i as u8;
+        }
+
+        let mut result = Vec::with_capacity(input.len() * 14 / 16);
+        let (mut bits, mut count, mut pending) = (0u32, 0, None);
+        for (i, c) in input.bytes().enumerate() {
+            let digit = match lookup[c as usize] {
+                255 => return Err(format!("invalid character {:?} at {}", c as char, i)),
+                d => d as u32,
+            };
+            let Some(low) = pending.take() else {
+                pending = Some(digit);
+                continue;
+            };
+            let value = low + digit * 91;
+            bits |= value << count;
+            count += if value & 8191 > 88 { 13 } else { 14 };
+            while count > 7 {
+                result.push(bits as u8);
+                bits >>= 8;
+                count -= 8;
+            }
+        }
+        if let Some(low) = pending {
+            result.push((bits | low << count) as u8);
+        }
+        Ok(result)
+    }
+}
+
 fn default_true() -> bool {
     true
 }
 
+/// A reversible built-in stage: transform, base85 charset and XOR key
+type Encoder<'a> = (&'a str, Option<&'a str>, Option<&'a str>);
+
 impl GlossConfig {
-    /// Apply the gloss transform to a record
-    pub async fn apply(&self, record: &str) -> Result<String> {
-        // Extract segment if pattern is configured
-        let input = if let Some(pattern) = &self.segment {
-            self.extract_segment(pattern, record)?
+    /// Files whose contents affect the result: plugins, JWT keys, and each
+    /// command's program and any arguments naming existing files
+    fn referenced_files(&self) -> Vec<&Path> {
+        let stages = std::iter::once((&self.command, &self.wasm, &self.jwt_key))
+            .chain((self.steps.iter()).map(|step| (&step.command, &step.wasm, &step.jwt_key)));
+        let mut files = Vec::new();
+        for (command, wasm, jwt_key) in stages {
+            files.extend(wasm.as_deref());
+            files.extend(jwt_key.as_deref());
+            if let Some((program, args)) = command.as_ref().and_then(|cmd| cmd.split_first()) {
+                files.push(Path::new(program));
+                files.extend((args.iter().map(Path::new)).filter(|arg| arg.is_file()));
+            }
+        }
+        files
+    }
+
+    /// The built-in transforms run by this gloss, in decode order with their
+    /// charsets and XOR keys, or an error naming the first stage that can't be reversed
+    fn encoder_chain(&self) -> Result<Vec<Encoder<'_>>> {
+        type Stage<'a> = (Option<&'a String>, Option<&'a String>, Option<&'a String>);
+        let stages: Vec<Stage> = if self.steps.is_empty() {
+            if self.transform_script.is_some() && self.transform.is_none() {
+                anyhow::bail!("gloss scripts can't be reversed");
+            }
+            if self.wasm.is_some() && self.transform.is_none() {
+                anyhow::bail!("gloss plugins can't be reversed");
+            }
+            if self.command.is_some() && self.transform.is_none() {
+                anyhow::bail!("gloss commands can't be reversed");
+            }
+            vec![(self.transform.as_ref(), None, None)]
         } else {
-            record.to_string()
+            self.steps
+                .iter()
+                .map(|step| {
+                    let charset = step.base85_charset.as_ref();
+                    (step.transform.as_ref(), charset, step.xor_key.as_ref())
+                })
+                .collect()
         };
 
+        let mut chain = Vec::new();
+        for (i, (transform, charset, xor_key)) in stages.into_iter().enumerate() {
+            let Some(transform) = transform else {
+                anyhow::bail!(
+                    "gloss step {} runs a command, script or plugin and can't be reversed",
+                    i + 1
+                );
+            };
+            if !ENCODERS.contains(&transform.as_str()) {
+                anyhow::bail!(
+                    "transform '{}' has no encoder (expected one of: {})",
+                    transform,
+                    ENCODERS.join(", ")
+                );
+            }
+            let charset = charset.or(self.base85_charset.as_ref());
+            let xor_key = xor_key.or(self.xor_key.as_ref());
+            chain.push((
+                transform.as_str(),
+                charset.map(String::as_str),
+                xor_key.map(String::as_str),
+            ));
+        }
+        Ok(chain)
+    }
+
+    /// Decode a record (or its segment) to bytes with the built-in transforms
+    pub fn decode(&self, input: &str) -> Result<Vec<u8>> {
+        let mut data = Payload::text(input);
+        for (transform, charset, xor_key) in self.encoder_chain()? {
+            data = run_builtin(transform, charset, None, xor_key, data)?;
+        }
+        Ok(data.bytes)
+    }
+
+    /// Encode bytes back to record text by running the transforms' encoders in reverse
+    pub fn encode(&self, bytes: &[u8]) -> Result<String> {
+        let mut data = bytes.to_vec();
+        for (transform, charset, xor_key) in self.encoder_chain()?.into_iter().rev() {
+            data = encode_builtin(transform, charset, xor_key, data)?;
+        }
+        String::from_utf8(data).context("encoded data is not text")
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout_ms.map(Duration::from_millis)
+    }
+
+    /// Run the gloss transform on a record (or its extracted segment), sending
+    /// commands to `helper` when running in server mode. Failures are returned
+    /// as errors; see `recover` for what is shown instead.
+    async fn run(&self, input: &str, helper: Option<&CoProcess>) -> Result<String> {
+        // A pipeline replaces transform/command
+        if !self.steps.is_empty() {
+            return self.run_steps(input).await;
+        }
+
         // Try built-in transform first
         if let Some(transform) = &self.transform {
-            return self.apply_builtin(transform, &input);
+            return self.apply_builtin(transform, input);
+        }
+
+        if let Some(source) = &self.transform_script {
+            return Ok(run_script(source, Payload::text(input))?.display());
+        }
+
+        if let Some(path) = &self.wasm {
+            return Ok(run_plugin(path, Payload::text(input))?.display());
         }
 
         // Try external command
         if let Some(cmd) = &self.command {
-            match self.apply_command(cmd, &input).await {
-                Ok(result) => return Ok(result),
-                Err(_) => {
-                    // Command failed - try fallback if configured
-                    if let Some(fallback) = &self.fallback {
-                        return self.apply_fallback(fallback, &input);
-                    }
-                    // No fallback - return input as-is with marker
-                    return Ok(format!("[decode failed] {}", input));
-                }
-            }
+            return match helper {
+                Some(helper) => helper.apply(input).await,
+                None => self.apply_command(cmd, input).await,
+            };
         }
 
-        Ok(input)
+        Ok(input.to_string())
+    }
+
+    /// Output for a record whose transform failed: the fallback if configured,
+    /// else the input marked "[decode failed]" for commands, else the error.
+    /// Built-in transforms, scripts and plugins always report their errors.
+    fn recover(&self, input: &str, err: anyhow::Error) -> Result<String> {
+        let builtin =
+            self.transform.is_some() || self.transform_script.is_some() || self.wasm.is_some();
+        if self.steps.is_empty() && builtin {
+            return Err(err);
+        }
+        match &self.fallback {
+            Some(fallback) => self.apply_fallback(fallback, input),
+            None if self.steps.is_empty() => Ok(format!("[decode failed] {}", input)),
+            None => Err(err),
+        }
     }
 
     /// Apply fallback transform when command fails
@@ -297,53 +1405,26 @@ impl GlossConfig {
         }
     }
 
-    /// Extract segment from record using regex pattern
-    fn extract_segment(&self, pattern: &str, record: &str) -> Result<String> {
-        let re = regex::Regex::new(pattern)
-            .map_err(|e| anyhow::anyhow!("invalid segment pattern: {}", e))?;
-
-        if let Some(caps) = re.captures(record) {
-            // Use first capture group, or whole match if no groups
-            let segment = caps
-                .get(1)
-                .or_else(|| caps.get(0))
-                .map(|m| m.as_str())
-                .unwrap_or(record);
-            Ok(segment.to_string())
-        } else {
-            // No match - return original record
-            Ok(record.to_string())
+    /// Run `[[gloss.steps]]` in order, passing bytes from one step to the next
+    async fn run_steps(&self, input: &str) -> Result<String> {
+        let mut data = Payload::text(input);
+        for (i, step) in self.steps.iter().enumerate() {
+            // Later steps only see the recovered part of a truncated stream, so keep the note
+            let truncated = data.truncated.take();
+            data = step
+                .run(data, self)
+                .await
+                .with_context(|| format!("gloss step {} ({})", i + 1, step.describe()))?;
+            data.truncated = truncated.or(data.truncated);
         }
+        Ok(data.display())
     }
 
     fn apply_builtin(&self, transform: &str, record: &str) -> Result<String> {
-        match transform {
-            "base85" => {
-                // Use configured charset or default to ASCII85
-                let charset = self
-                    .base85_charset
-                    .as_ref()
-                    .and_then(|name| base85_charsets::get(name))
-                    .unwrap_or(base85_charsets::ASCII85);
-
-                let bytes = base85_charsets::decode(record, charset)
-                    .map_err(|e| anyhow::anyhow!("base85 decode error: {}", e))?;
-                Ok(hex::encode(&bytes))
-            }
-            "base64" => {
-                use base64::Engine;
-                let bytes = base64::engine::general_purpose::STANDARD
-                    .decode(record)
-                    .context("base64 decode error")?;
-                Ok(hex::encode(&bytes))
-            }
-            "hex" => {
-                // Already hex, just clean it up
-                Ok(record.replace([' ', '\n', '\r'], ""))
-            }
-            "none" | "" => Ok(record.to_string()),
-            _ => Err(anyhow::anyhow!("unknown transform: {}", transform)),
-        }
+        let charset = self.base85_charset.as_deref();
+        let key = self.jwt_key.as_deref();
+        let xor_key = self.xor_key.as_deref();
+        Ok(run_builtin(transform, charset, key, xor_key, Payload::text(record))?.display())
     }
 
     async fn apply_command(&self, cmd: &[String], record: &str) -> Result<String> {
@@ -351,14 +1432,16 @@ impl GlossConfig {
             return Ok(record.to_string());
         }
 
-        let output = Command::new(&cmd[0])
+        let run = Command::new(&cmd[0])
             .args(&cmd[1..])
             .arg(record)
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .output()
-            .await
+            .kill_on_drop(true)
+            .output();
+        let output = with_timeout(self.timeout(), &cmd[0], run)
+            .await?
             .context("failed to run gloss command")?;
 
         if output.status.success() {
@@ -386,32 +1469,786 @@ pub struct FieldExtractor {
     /// Field name
     pub name: String,
     /// Regex pattern with capture group
+    #[serde(default)]
     pub pattern: String,
+    /// JSON path such as `$.item.rarity`, used instead of `pattern` on JSON text
+    #[serde(default)]
+    pub json: Option<String>,
+    /// Rhai script computing the value from `record`, `gloss` and earlier `fields`
+    #[serde(default)]
+    pub script: Option<String>,
+    /// WebAssembly plugin decoding the raw record (or gloss output) to the value
+    #[serde(default)]
+    pub wasm: Option<PathBuf>,
     /// Extract from glossed output instead of raw
     #[serde(default)]
     pub from_gloss: bool,
 }
 
+/// Expected results for a sample record, checked by `lw preset test`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresetTest {
+    /// Sample input record
+    pub input: String,
+    /// Whether the detect rules should match the input
+    #[serde(default = "default_true")]
+    pub detect: bool,
+    /// Expected gloss output
+    #[serde(default)]
+    pub gloss: Option<String>,
+    /// Expected field values by name (`[[fields]]` and `[[layout]]`)
+    #[serde(default)]
+    pub fields: std::collections::BTreeMap<String, String>,
+}
+
+/// Fixture file holding `[[test]]` entries, e.g. a `<preset>.tests.toml` sidecar
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PresetFixtures {
+    #[serde(default, rename = "test")]
+    pub tests: Vec<PresetTest>,
+}
+
+/// Typed field at a fixed byte offset in the decoded record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutField {
+    /// Field name
+    pub name: String,
+    /// Byte offset from the start of the decoded record
+    pub offset: usize,
+    /// Data type name, as used in `.lwpreset` files: "u16le", "varint", "utf8:nul", ...
+    #[serde(rename = "type")]
+    pub data_type: String,
+    /// Byte length, required for `fixed` strings and blobs
+    #[serde(default)]
+    pub length: Option<usize>,
+}
+
+impl LayoutField {
+    /// Decode this field from a record's bytes (empty if out of range)
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let Some(dtype) = DataType::from_name(&self.data_type) else {
+            return format!("[unknown type {}]", self.data_type);
+        };
+        let end = match self.length {
+            Some(len) => self.offset.saturating_add(len).min(bytes.len()),
+            None => bytes.len(),
+        };
+        bytes
+            .get(self.offset..end)
+            .map(|data| dtype.decode(data))
+            .unwrap_or_default()
+    }
+}
+
+/// Bytes behind a gloss result, for layout fields: hex output is decoded,
+/// anything else is used as-is. A `[truncated: ...]` marker is skipped.
+pub fn gloss_bytes(gloss_output: &str) -> Vec<u8> {
+    let gloss_output = gloss_output
+        .strip_prefix("[truncated: ")
+        .and_then(|rest| rest.split_once("] "))
+        .map_or(gloss_output, |(_, data)| data);
+    let cleaned = gloss_output.replace([' ', '\n', '\r'], "");
+    hex::decode(&cleaned).unwrap_or_else(|_| gloss_output.as_bytes().to_vec())
+}
+
+/// How a `[[fields]]` entry finds its value
+#[derive(Debug, Clone)]
+enum FieldMatcher {
+    Regex(Regex),
+    Json(JsonPath),
+    Script(Arc<Script>),
+    Wasm(Arc<Plugin>),
+}
+
+/// Run a computed field's script with the record, gloss output and the fields
+/// declared before it in scope
+fn field_script(
+    script: &Script,
+    record: &str,
+    glossed: Option<&str>,
+    earlier: &[(String, String)],
+) -> String {
+    let fields: rhai::Map = (earlier.iter())
+        .map(|(name, value)| (name.as_str().into(), value.clone().into()))
+        .collect();
+    let vars = vec![
+        ("record", record.into()),
+        ("gloss", glossed.unwrap_or_default().into()),
+        ("fields", fields.into()),
+    ];
+    match script.eval(vars) {
+        Ok(value) => script::to_text(value),
+        Err(e) => format!("[error] {}", e),
+    }
+}
+
+/// A JSON path such as `$.item.rarity`, `$.parts[0]` or `$["odd key"]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath(Vec<PathStep>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, String> {
+        let mut rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| "path must start with '$'".to_string())?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err("empty key after '.'".to_string());
+                }
+                steps.push(PathStep::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| "unclosed '['".to_string())?;
+                let inner = &after[..end];
+                let quoted = ['"', '\'']
+                    .iter()
+                    .find_map(|&q| inner.strip_prefix(q)?.strip_suffix(q));
+                steps.push(match quoted {
+                    Some(key) => PathStep::Key(key.to_string()),
+                    None => PathStep::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| format!("invalid index '[{}]'", inner))?,
+                    ),
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(format!("expected '.' or '[' at {:?}", rest));
+            }
+        }
+        Ok(Self(steps))
+    }
+
+    pub fn select<'v>(&self, value: &'v serde_json::Value) -> Option<&'v serde_json::Value> {
+        self.0.iter().try_fold(value, |value, step| match step {
+            PathStep::Key(key) => value.get(key),
+            PathStep::Index(index) => value.get(index),
+        })
+    }
+}
+
+/// A JSON value as a column: strings unquoted, null empty, anything else compact JSON
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// First capture group of the first match, or the whole match if there are no groups
+fn capture<'t>(re: &Regex, text: &'t str) -> Option<&'t str> {
+    let caps = re.captures(text)?;
+    caps.get(1).or_else(|| caps.get(0)).map(|m| m.as_str())
+}
+
+/// Byte ranges of `capture` for every non-overlapping match
+fn capture_spans(re: &Regex, text: &str) -> Vec<Range<usize>> {
+    re.captures_iter(text)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(0)))
+        .map(|m| m.range())
+        .collect()
+}
+
+/// Gloss results for one record, one per output row
+pub type Glossed = Vec<Result<String>>;
+
+/// A gloss config ready to run, with its segment pattern compiled
+#[derive(Debug, Clone)]
+pub struct CompiledGloss {
+    pub config: GlossConfig,
+    segment: Option<Regex>,
+    /// Preset name, serialized config and referenced file stamps, prefixing
+    /// cache keys; unset with `cache = false`
+    cache_scope: Option<String>,
+    /// Whether results are also cached on disk: only slow decoders (commands,
+    /// servers and plugins) are worth a file per result
+    persist: bool,
+    /// The running `server` command, shared by clones
+    helper: Option<Arc<CoProcess>>,
+}
+
+impl CompiledGloss {
+    pub fn new(config: GlossConfig) -> Result<Self> {
+        let segment = config
+            .segment
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .context("invalid segment pattern")?;
+        let scripts = std::iter::once(&config.transform_script)
+            .chain(config.steps.iter().map(|step| &step.transform_script));
+        for source in scripts.flatten() {
+            script::compile(source).context("invalid transform_script")?;
+        }
+        let plugins =
+            std::iter::once(&config.wasm).chain(config.steps.iter().map(|step| &step.wasm));
+        for path in plugins.flatten() {
+            plugin::load(path)?;
+        }
+        let helper = match (&config.server, &config.command) {
+            (Some(framing), Some(cmd)) if !cmd.is_empty() => Some(Arc::new(CoProcess::new(
+                cmd.clone(),
+                *framing,
+                config.timeout(),
+            ))),
+            _ => None,
+        };
+        let cache_scope = config
+            .cache
+            .then(|| -> Result<String> {
+                let stamps = cache::file_stamps(config.referenced_files());
+                Ok(format!("{}\n{}", serde_json::to_string(&config)?, stamps))
+            })
+            .transpose()?;
+        let persist = std::iter::once((&config.command, &config.wasm))
+            .chain(config.steps.iter().map(|step| (&step.command, &step.wasm)))
+            .any(|(command, wasm)| command.is_some() || wasm.is_some());
+        Ok(Self {
+            config,
+            segment,
+            cache_scope,
+            persist,
+            helper,
+        })
+    }
+
+    /// Key cached results by the preset as well as the gloss config
+    pub fn in_preset(mut self, name: &str) -> Self {
+        if let Some(scope) = &mut self.cache_scope {
+            *scope = format!("{}\n{}", name, scope);
+        }
+        self
+    }
+
+    /// The part of a record the transform sees: the captured segment, or the
+    /// whole record if there is no segment pattern or it doesn't match
+    pub fn segment<'t>(&self, record: &'t str) -> &'t str {
+        self.segment
+            .as_ref()
+            .and_then(|re| capture(re, record))
+            .unwrap_or(record)
+    }
+
+    /// Gloss a record, using and filling the result cache. There is one result per
+    /// output row: several with `segments = "rows"`, otherwise one.
+    pub async fn apply(&self, record: &str) -> Glossed {
+        self.apply_segments(record, true).await
+    }
+
+    /// Gloss a record like `apply`, bypassing the cache
+    pub async fn apply_uncached(&self, record: &str) -> Glossed {
+        self.apply_segments(record, false).await
+    }
+
+    async fn apply_segments(&self, record: &str, cached: bool) -> Glossed {
+        let spans = (self.segment.as_ref()).map_or_else(Vec::new, |re| capture_spans(re, record));
+        let gloss = |input| async move {
+            if cached {
+                self.apply_one(input).await
+            } else {
+                self.apply_one_uncached(input).await
+            }
+        };
+
+        match self.config.segments {
+            // A line without segments is left as it is
+            SegmentMode::Inline | SegmentMode::Rows if spans.is_empty() => {
+                vec![Ok(record.to_string())]
+            }
+            SegmentMode::Inline => {
+                let mut annotated = String::new();
+                let mut last = 0;
+                for span in spans {
+                    // A segment that fails to gloss is left as it is
+                    let Ok(glossed) = gloss(&record[span.clone()]).await else {
+                        continue;
+                    };
+                    annotated.push_str(&record[last..span.end]);
+                    annotated.push_str(&format!("({})", glossed));
+                    last = span.end;
+                }
+                annotated.push_str(&record[last..]);
+                vec![Ok(annotated)]
+            }
+            SegmentMode::Rows => {
+                let mut results = Vec::new();
+                for span in spans {
+                    results.push(gloss(&record[span]).await);
+                }
+                results
+            }
+            SegmentMode::First => vec![gloss(self.segment(record)).await],
+        }
+    }
+
+    /// Gloss one segment, using and filling the result cache
+    async fn apply_one(&self, input: &str) -> Result<String> {
+        let cache = GlossCache::global();
+        let key = (self.cache_scope.as_ref()).map(|scope| GlossCache::key(scope, input));
+        if let Some(hit) = key.as_ref().and_then(|key| cache.get(key, self.persist)) {
+            return Ok(hit);
+        }
+
+        match self.run(input).await {
+            Ok(result) => {
+                // Only successes are cached, so a failing decoder is retried next time
+                if let Some(key) = &key {
+                    cache.put(key, &result, self.persist);
+                }
+                Ok(result)
+            }
+            Err(e) => self.config.recover(input, e),
+        }
+    }
+
+    async fn apply_one_uncached(&self, input: &str) -> Result<String> {
+        match self.run(input).await {
+            Ok(result) => Ok(result),
+            Err(e) => self.config.recover(input, e),
+        }
+    }
+
+    /// Run the transform, checking that JSON output parses
+    async fn run(&self, input: &str) -> Result<String> {
+        let result = self.config.run(input, self.helper.as_deref()).await?;
+        if self.config.output == GlossOutput::Json {
+            serde_json::from_str::<serde_json::Value>(&result)
+                .context("gloss output is not JSON")?;
+        }
+        Ok(result)
+    }
+}
+
+/// Gloss jobs for a sequence of input lines, run concurrently (at most `limit`
+/// at a time) on the tokio runtime, with results handed back in input order
+pub struct GlossQueue<T> {
+    limit: usize,
+    pending: VecDeque<(T, Option<JoinHandle<Glossed>>)>,
+}
+
+impl<T> GlossQueue<T> {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Queue a line, glossing `record` if a gloss is given. If `limit` lines
+    /// are already waiting, the oldest is finished first and returned.
+    pub async fn push(
+        &mut self,
+        item: T,
+        job: Option<(Arc<CompiledGloss>, String)>,
+    ) -> Result<Option<(T, Option<Glossed>)>> {
+        let done = if self.pending.len() >= self.limit {
+            self.pop().await?
+        } else {
+            None
+        };
+        let handle =
+            job.map(|(gloss, record)| tokio::spawn(async move { gloss.apply(&record).await }));
+        self.pending.push_back((item, handle));
+        Ok(done)
+    }
+
+    /// Finish the oldest queued line, if any
+    pub async fn pop(&mut self) -> Result<Option<(T, Option<Glossed>)>> {
+        let Some((item, handle)) = self.pending.pop_front() else {
+            return Ok(None);
+        };
+        let result = match handle {
+            Some(handle) => Some(handle.await.context("gloss task failed")?),
+            None => None,
+        };
+        Ok(Some((item, result)))
+    }
+}
+
+/// A preset with every regex compiled, built once at load time and used for
+/// detection, segmenting, coloring and field extraction
+#[derive(Debug, Clone)]
+pub struct CompiledPreset {
+    pub preset: Preset,
+    pub gloss: Option<Arc<CompiledGloss>>,
+    /// One entry per detect rule
+    detect: Vec<Matcher>,
+    /// Specificity of each detect rule, in bits
+    weights: Vec<f64>,
+    /// One entry per `[[fields]]` extractor
+    fields: Vec<FieldMatcher>,
+    /// One regex per `[[color]]` rule
+    color: Vec<Regex>,
+    /// Top-level JSON gloss columns, named by the first glossed record and
+    /// shared by clones so every row has the same columns
+    json_columns: Arc<OnceLock<Vec<String>>>,
+}
+
+impl CompiledPreset {
+    pub fn new(preset: Preset) -> Result<Self> {
+        let detect = preset
+            .detect
+            .iter()
+            .map(DetectRule::compile)
+            .collect::<Result<_>>()?;
+        let weights = preset.detect.iter().map(DetectRule::specificity).collect();
+        let gloss = (preset.gloss.clone())
+            .map(|config| {
+                CompiledGloss::new(config).map(|g| Arc::new(g.in_preset(&preset.preset.name)))
+            })
+            .transpose()?;
+        let color = preset
+            .color
+            .iter()
+            .map(|rule| {
+                Regex::new(&rule.pattern)
+                    .with_context(|| format!("invalid color pattern {:?}", rule.pattern))
+            })
+            .collect::<Result<_>>()?;
+        let fields = preset
+            .fields
+            .iter()
+            .map(|field| match (&field.script, &field.json) {
+                (Some(source), _) => script::compile(source)
+                    .map(FieldMatcher::Script)
+                    .with_context(|| format!("invalid script for field '{}'", field.name)),
+                (None, _) if field.wasm.is_some() => plugin::load(field.wasm.as_ref().unwrap())
+                    .map(FieldMatcher::Wasm)
+                    .with_context(|| format!("invalid plugin for field '{}'", field.name)),
+                (None, Some(path)) => JsonPath::parse(path).map(FieldMatcher::Json).map_err(|e| {
+                    anyhow::anyhow!("invalid JSON path for field '{}': {}", field.name, e)
+                }),
+                (None, None) => Regex::new(&field.pattern)
+                    .map(FieldMatcher::Regex)
+                    .with_context(|| format!("invalid pattern for field '{}'", field.name)),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            preset,
+            gloss,
+            detect,
+            weights,
+            color,
+            fields,
+            json_columns: Arc::default(),
+        })
+    }
+
+    /// Check if a record satisfies every detect rule (presets without rules never match)
+    pub fn matches(&self, record: &[u8]) -> bool {
+        !self.detect.is_empty() && self.detect.iter().all(|rule| rule.matches(record))
+    }
+
+    /// Which detect rules a record satisfies, in declaration order
+    pub fn rule_matches(&self, record: &[u8]) -> Vec<bool> {
+        self.detect
+            .iter()
+            .map(|rule| rule.matches(record))
+            .collect()
+    }
+
+    /// Specificity of each detect rule, in bits
+    pub fn rule_weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Total specificity of the detect rules: how unlikely a chance match is
+    pub fn specificity(&self) -> f64 {
+        self.weights.iter().sum()
+    }
+
+    /// Whether any `[[fields]]` extractor reads the gloss output
+    fn has_gloss_fields(&self) -> bool {
+        self.preset.fields.iter().any(|f| f.from_gloss)
+    }
+
+    /// Whether the gloss output is JSON (`[gloss] output = "json"`)
+    fn json_gloss(&self) -> bool {
+        (self.gloss.as_ref()).is_some_and(|g| g.config.output == GlossOutput::Json)
+    }
+
+    /// Whether glossed records are shown as columns rather than as-is
+    pub fn extracts_fields(&self) -> bool {
+        !self.preset.fields.is_empty() || !self.preset.layout.is_empty() || self.json_gloss()
+    }
+
+    /// Named columns for a record: `[[fields]]` in declaration order, each read from
+    /// the raw record or the gloss output, then layout fields. JSON gloss output
+    /// without gloss fields adds one column per top-level value, named by its key or
+    /// index in the first glossed record; later records fill the same columns,
+    /// leaving missing ones empty and dropping any others. Without gloss output
+    /// only the raw-side fields are extracted, plus layout fields from the raw
+    /// record if the preset has no gloss.
+    pub fn columns(&self, record: &str, glossed: Option<&str>) -> Vec<(String, String)> {
+        let mut columns = self.field_columns(record, glossed);
+        let layout = self.preset.layout.iter().map(|field| field.name.clone());
+        let Some(glossed) = glossed else {
+            if self.gloss.is_none() {
+                columns.extend(layout.zip(self.preset.decode_layout(record.as_bytes())));
+            }
+            return columns;
+        };
+
+        if !self.has_gloss_fields() && self.json_gloss() {
+            let values: Vec<(String, String)> = match serde_json::from_str(glossed) {
+                Ok(serde_json::Value::Object(map)) => {
+                    map.iter().map(|(k, v)| (k.clone(), json_text(v))).collect()
+                }
+                Ok(serde_json::Value::Array(items)) => (items.iter().enumerate())
+                    .map(|(i, v)| (i.to_string(), json_text(v)))
+                    .collect(),
+                Ok(other) => vec![("gloss".to_string(), json_text(&other))],
+                Err(_) => vec![("gloss".to_string(), glossed.to_string())],
+            };
+            let names = (self.json_columns)
+                .get_or_init(|| values.iter().map(|(name, _)| name.clone()).collect());
+            columns.extend(names.iter().map(|name| {
+                let value = values.iter().find(|(key, _)| key == name);
+                (
+                    name.clone(),
+                    value.map(|(_, v)| v.clone()).unwrap_or_default(),
+                )
+            }));
+        }
+        columns.extend(layout.zip(self.preset.decode_layout(&gloss_bytes(glossed))));
+        columns
+    }
+
+    /// `[[fields]]` values in declaration order. Gloss-side fields are left out
+    /// when there is no gloss output.
+    fn field_columns(&self, record: &str, glossed: Option<&str>) -> Vec<(String, String)> {
+        // Parsed on first use, so several JSON fields share one parse
+        let (raw_json, gloss_json) = (OnceCell::new(), OnceCell::new());
+        let mut columns = Vec::new();
+        for (field, matcher) in self.preset.fields.iter().zip(&self.fields) {
+            let (text, parsed) = match (field.from_gloss, glossed) {
+                (false, _) => (record, &raw_json),
+                (true, Some(glossed)) => (glossed, &gloss_json),
+                (true, None) => continue,
+            };
+            let value = match matcher {
+                FieldMatcher::Regex(re) => capture(re, text).unwrap_or_default().to_string(),
+                FieldMatcher::Json(path) => parsed
+                    .get_or_init(|| serde_json::from_str::<serde_json::Value>(text).ok())
+                    .as_ref()
+                    .and_then(|value| path.select(value))
+                    .map(json_text)
+                    .unwrap_or_default(),
+                FieldMatcher::Script(script) => field_script(script, record, glossed, &columns),
+                FieldMatcher::Wasm(plugin) => match plugin.call(text.as_bytes()) {
+                    Ok(output) => Payload::output(output).display(),
+                    Err(e) => format!("[error] {:#}", e),
+                },
+            };
+            columns.push((field.name.clone(), value));
+        }
+        columns
+    }
+
+    /// Extract one field by name from the raw record or gloss output, as it declares
+    pub fn field_value(&self, name: &str, record: &str, glossed: &str) -> Option<String> {
+        (self.field_columns(record, Some(glossed)).into_iter())
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Spans of `text` matched by `[[color]]` rules, with the style to apply,
+    /// in order; earlier rules win where matches overlap
+    #[allow(dead_code)] // No output mode styles text yet
+    pub fn color_spans(&self, text: &str) -> Vec<(Range<usize>, &str)> {
+        let mut spans: Vec<(Range<usize>, &str)> = Vec::new();
+        for (re, rule) in self.color.iter().zip(&self.preset.color) {
+            for m in re.find_iter(text).filter(|m| !m.is_empty()) {
+                if !spans
+                    .iter()
+                    .any(|(s, _)| m.start() < s.end && s.start < m.end())
+                {
+                    spans.push((m.range(), rule.style.as_str()));
+                }
+            }
+        }
+        spans.sort_by_key(|(span, _)| span.start);
+        spans
+    }
+}
+
 /// Preset manager - loads and caches presets
 #[derive(Debug, Default)]
 pub struct PresetManager {
-    presets: HashMap<String, Preset>,
+    presets: HashMap<String, CompiledPreset>,
+    /// Raw TOML of every loaded preset, used to resolve `extends`
+    sources: HashMap<String, toml::Value>,
+    /// File each user preset was loaded from
+    paths: HashMap<String, PathBuf>,
     search_paths: Vec<PathBuf>,
 }
 
+/// Name of the preset this source extends, if any
+fn parent_name(source: &toml::Value) -> Option<&str> {
+    source.get("preset")?.get("extends")?.as_str()
+}
+
+/// Make the `wasm` and `jwt_key` paths in a preset source (gloss, its steps
+/// and fields) relative to `dir`, the directory of the preset file
+pub fn resolve_file_paths(source: &mut toml::Value, dir: &Path) {
+    fn resolve(table: &mut toml::Value, dir: &Path) {
+        for key in ["wasm", "jwt_key"] {
+            if let Some(toml::Value::String(path)) = table.get_mut(key) {
+                *path = dir.join(&*path).to_string_lossy().into_owned();
+            }
+        }
+    }
+    fn each(array: Option<&mut toml::Value>, dir: &Path) {
+        if let Some(toml::Value::Array(tables)) = array {
+            tables.iter_mut().for_each(|table| resolve(table, dir));
+        }
+    }
+    if let Some(gloss) = source.get_mut("gloss") {
+        resolve(gloss, dir);
+        each(gloss.get_mut("steps"), dir);
+    }
+    each(source.get_mut("fields"), dir);
+}
+
+/// `[gloss]` keys that choose how a record is glossed; a child setting any of
+/// these replaces all of the parent's
+const GLOSS_KINDS: &[&str] = &["transform", "transform_script", "wasm", "command", "steps"];
+
+/// Apply a child preset's source on top of its resolved parent.
+///
+/// - `[preset]` metadata and `[[test]]` fixtures are never inherited
+/// - Tables (`[records]`, `[gloss]`) merge key by key, child keys win; a
+///   child `[gloss]` naming any gloss kind replaces the parent's kind entirely
+/// - `[[fields]]` and `[[layout]]` entries replace the parent entry with the
+///   same `name`, `[[color]]` entries the one with the same `match`; others append
+/// - `[[detect]]` rules append, so children refine the parent's detection
+fn inherit(parent: toml::Value, child: &toml::Value) -> toml::Value {
+    use toml::Value;
+
+    let (Value::Table(mut merged), Value::Table(child)) = (parent, child) else {
+        return child.clone();
+    };
+    merged.remove("preset");
+    merged.remove("test");
+
+    for (key, value) in child {
+        let inherited = merged.remove(key);
+        let value = match (key.as_str(), inherited, value) {
+            ("fields" | "layout", Some(Value::Array(base)), Value::Array(over)) => {
+                Value::Array(merge_keyed(base, over, "name"))
+            }
+            ("color", Some(Value::Array(base)), Value::Array(over)) => {
+                Value::Array(merge_keyed(base, over, "match"))
+            }
+            ("detect", Some(Value::Array(mut base)), Value::Array(over)) => {
+                base.extend(over.iter().cloned());
+                Value::Array(base)
+            }
+            (_, Some(Value::Table(mut base)), Value::Table(over)) => {
+                if key == "gloss" && GLOSS_KINDS.iter().any(|k| over.contains_key(*k)) {
+                    base.retain(|k, _| !GLOSS_KINDS.contains(&k));
+                }
+                for (k, v) in over {
+                    base.insert(k.clone(), v.clone());
+                }
+                Value::Table(base)
+            }
+            (_, _, value) => value.clone(),
+        };
+        merged.insert(key.clone(), value);
+    }
+
+    Value::Table(merged)
+}
+
+/// Merge arrays of tables: entries sharing `key` are merged in place, others appended
+fn merge_keyed(mut base: Vec<toml::Value>, over: &[toml::Value], key: &str) -> Vec<toml::Value> {
+    for entry in over {
+        let id = entry.get(key);
+        let existing = id.and_then(|id| base.iter_mut().find(|b| b.get(key) == Some(id)));
+        match (existing, entry) {
+            (Some(toml::Value::Table(target)), toml::Value::Table(fields)) => {
+                for (k, v) in fields {
+                    target.insert(k.clone(), v.clone());
+                }
+            }
+            _ => base.push(entry.clone()),
+        }
+    }
+    base
+}
+
+/// `.toml` files in a preset directory, excluding `<name>.tests.toml` fixture sidecars
+fn is_preset_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    name.ends_with(".toml") && !name.ends_with(".tests.toml")
+}
+
+/// Built-in gloss transforms accepted by `GlossConfig::apply_builtin`
+pub const BUILTIN_TRANSFORMS: &[&str] = &[
+    "base85",
+    "base64",
+    "base64url",
+    "base32",
+    "base58",
+    "base91",
+    "url",
+    "quoted_printable",
+    "hex",
+    "hex_decode",
+    "zlib",
+    "gzip",
+    "deflate",
+    "lz4",
+    "zstd",
+    "jwt",
+    "xor",
+    "xor_rolling",
+    "none",
+];
+
+/// Fallbacks accepted by `GlossConfig::apply_fallback`
+pub const FALLBACKS: &[&str] = &["input", "hex", "base85", "base64"];
+
 /// Embedded default presets (compiled into binary)
 mod embedded_presets {
     pub const ASCII85: &str = include_str!("../presets/ascii85.toml");
+    pub const BASE32: &str = include_str!("../presets/base32.toml");
+    pub const BASE58: &str = include_str!("../presets/base58.toml");
     pub const BASE64: &str = include_str!("../presets/base64.toml");
+    pub const BASE64URL: &str = include_str!("../presets/base64url.toml");
+    pub const BASE91: &str = include_str!("../presets/base91.toml");
     pub const HEX: &str = include_str!("../presets/hex.toml");
     pub const JWT: &str = include_str!("../presets/jwt.toml");
+    pub const QUOTED_PRINTABLE: &str = include_str!("../presets/quoted-printable.toml");
+    pub const URL: &str = include_str!("../presets/url.toml");
     pub const Z85: &str = include_str!("../presets/z85.toml");
 
     pub const ALL: &[(&str, &str)] = &[
         ("ascii85", ASCII85),
+        ("base32", BASE32),
+        ("base58", BASE58),
         ("base64", BASE64),
+        ("base64url", BASE64URL),
+        ("base91", BASE91),
         ("hex", HEX),
         ("jwt", JWT),
+        ("quoted-printable", QUOTED_PRINTABLE),
+        ("url", URL),
         ("z85", Z85),
     ];
 }
@@ -443,6 +2280,11 @@ impl PresetManager {
 
     /// Load all presets (embedded defaults + search paths)
     pub fn load_all(&mut self) -> Result<()> {
+        self.load_all_with(&[])
+    }
+
+    /// Load all presets, plus preset files named on the command line
+    pub fn load_all_with(&mut self, files: &[&Path]) -> Result<()> {
         // Load embedded presets first (can be overridden by user presets)
         self.load_embedded();
 
@@ -452,19 +2294,22 @@ impl PresetManager {
                 self.load_from_dir(path)?;
             }
         }
+
+        // Explicit files override everything
+        for path in files {
+            self.load_preset(path)
+                .with_context(|| format!("failed to load preset {:?}", path))?;
+        }
+
+        self.resolve_inheritance();
         Ok(())
     }
 
     /// Load embedded default presets
     fn load_embedded(&mut self) {
         for (name, content) in embedded_presets::ALL {
-            match toml::from_str::<Preset>(content) {
-                Ok(preset) => {
-                    self.presets.insert(name.to_string(), preset);
-                }
-                Err(e) => {
-                    eprintln!("Warning: failed to parse embedded preset '{}': {}", name, e);
-                }
+            if let Err(e) = self.add_source(name, content, None) {
+                eprintln!("Warning: failed to parse embedded preset '{}': {}", name, e);
             }
         }
     }
@@ -478,7 +2323,7 @@ impl PresetManager {
 
         for entry in entries.flatten() {
             let path = entry.path();
-            if path.extension().map(|e| e == "toml").unwrap_or(false) {
+            if is_preset_file(&path) {
                 if let Err(e) = self.load_preset(&path) {
                     eprintln!("Warning: failed to load preset {:?}: {}", path, e);
                 }
@@ -488,28 +2333,142 @@ impl PresetManager {
         Ok(())
     }
 
+    /// Every preset file in the search paths, whether or not it loaded
+    pub fn preset_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self
+            .search_paths
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.flatten().map(|e| e.path()))
+            .filter(|path| is_preset_file(path))
+            .collect();
+        files.sort();
+        files
+    }
+
     /// Load a single preset file
     pub fn load_preset(&mut self, path: &Path) -> Result<()> {
         let content = fs::read_to_string(path).context("failed to read preset file")?;
-        let preset: Preset = toml::from_str(&content).context("failed to parse preset")?;
-        let name = preset.preset.name.clone();
-        if name.is_empty() {
-            let name = path
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_default();
-            self.presets.insert(name, preset);
-        } else {
-            self.presets.insert(name, preset);
-        }
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = self.add_source(&stem, &content, path.parent())?;
+        self.paths.insert(name, path.to_path_buf());
         Ok(())
     }
 
+    /// Register a preset's TOML source under its declared name (or `default_name`),
+    /// with `wasm` and `jwt_key` paths made relative to `dir`. Presets using `extends` are only
+    /// validated once inheritance is resolved.
+    fn add_source(
+        &mut self,
+        default_name: &str,
+        content: &str,
+        dir: Option<&Path>,
+    ) -> Result<String> {
+        let mut source: toml::Value = toml::from_str(content).context("failed to parse preset")?;
+        if let Some(dir) = dir {
+            resolve_file_paths(&mut source, dir);
+        }
+        let name = source
+            .get("preset")
+            .and_then(|p| p.get("name"))
+            .and_then(|n| n.as_str())
+            .filter(|n| !n.is_empty())
+            .unwrap_or(default_name)
+            .to_string();
+
+        if parent_name(&source).is_none() {
+            let preset: Preset = source
+                .clone()
+                .try_into()
+                .context("failed to parse preset")?;
+            self.presets
+                .insert(name.clone(), CompiledPreset::new(preset)?);
+        }
+        self.sources.insert(name.clone(), source);
+        Ok(name)
+    }
+
+    /// Build every preset that declares `extends`, reporting missing parents and cycles
+    fn resolve_inheritance(&mut self) {
+        let mut children: Vec<String> = self
+            .sources
+            .iter()
+            .filter(|(_, source)| parent_name(source).is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+        children.sort();
+
+        for name in children {
+            match self.resolve(&name).and_then(CompiledPreset::new) {
+                Ok(preset) => {
+                    self.presets.insert(name, preset);
+                }
+                Err(e) => {
+                    self.presets.remove(&name);
+                    eprintln!("Warning: failed to load preset '{}': {:#}", name, e);
+                }
+            }
+        }
+    }
+
+    /// Build a preset from its source and its `extends` chain
+    pub fn resolve(&self, name: &str) -> Result<Preset> {
+        self.resolve_source(name, &mut Vec::new())?
+            .try_into()
+            .context("failed to parse preset")
+    }
+
+    /// Merge a preset's source over its ancestors
+    fn resolve_source(&self, name: &str, chain: &mut Vec<String>) -> Result<toml::Value> {
+        if chain.iter().any(|n| n == name) {
+            chain.push(name.to_string());
+            anyhow::bail!("inheritance cycle: {}", chain.join(" -> "));
+        }
+        let source = self
+            .sources
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("unknown parent preset '{}'", name))?;
+        let Some(parent) = parent_name(source) else {
+            return Ok(source.clone());
+        };
+
+        chain.push(name.to_string());
+        let base = self.resolve_source(parent, chain)?;
+        chain.pop();
+        Ok(inherit(base, source))
+    }
+
     /// Get a preset by name
     pub fn get(&self, name: &str) -> Option<&Preset> {
+        self.presets.get(name).map(|c| &c.preset)
+    }
+
+    /// Get a preset by name, with its regexes compiled
+    pub fn compiled(&self, name: &str) -> Option<&CompiledPreset> {
         self.presets.get(name)
     }
 
+    /// Names and TOML sources of the presets compiled into the binary
+    pub fn embedded() -> &'static [(&'static str, &'static str)] {
+        embedded_presets::ALL
+    }
+
+    /// File a preset was loaded from (`None` for embedded presets)
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.paths.get(name).map(|p| p.as_path())
+    }
+
+    /// Name of the preset loaded from `path`
+    pub fn name_for(&self, path: &Path) -> Option<&str> {
+        self.paths
+            .iter()
+            .find(|(_, p)| p.as_path() == path)
+            .map(|(name, _)| name.as_str())
+    }
+
     /// List all loaded presets
     pub fn list(&self) -> Vec<&str> {
         self.presets.keys().map(|s| s.as_str()).collect()
@@ -565,4 +2524,696 @@ style = "green"
         assert!(rule.matches(b"12345"));
         assert!(!rule.matches(b"1234"));
     }
+
+    #[test]
+    fn test_combinator_rules() {
+        let preset: Preset = toml::from_str(
+            r#"
+[[detect]]
+type = "any"
+rules = [
+    { type = "bytes", pattern = "21 ?? 00 ff", offset = 0 },
+    { type = "bytes", pattern = "ca fe" },
+]
+
+[[detect]]
+type = "not"
+rule = { type = "length_equals", value = 3 }
+
+[[detect]]
+type = "length_modulo"
+modulus = 2
+"#,
+        )
+        .unwrap();
+        let compiled = CompiledPreset::new(preset).unwrap();
+
+        assert!(compiled.matches(b"\x21\x07\x00\xff"));
+        assert!(compiled.matches(b"xx\xca\xfe"));
+        assert!(!compiled.matches(b"\x21\x07\x01\xff"));
+        assert!(!compiled.matches(b"x\xca\xfe"), "odd length");
+        assert_eq!(
+            compiled.rule_matches(b"\xca\xfe\x00"),
+            vec![true, false, false]
+        );
+
+        assert_eq!(parse_byte_pattern("21??").unwrap(), vec![Some(0x21), None]);
+        assert!(parse_byte_pattern("2 1 0").is_err());
+        assert!(parse_byte_pattern("zz").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gloss_steps() {
+        let gloss: GlossConfig = toml::from_str(
+            r#"
+[[steps]]
+transform = "base64"
+
+[[steps]]
+command = ["cat"]
+
+[[steps]]
+transform = "hex_decode"
+"#,
+        )
+        .unwrap();
+
+        // base64 of the text "68656c6c6f": bytes flow through cat, then hex-decode to "hello"
+        assert_eq!(
+            gloss.run("Njg2NTZjNmM2Zg==", None).await.unwrap(),
+            "68656c6c6f"
+        );
+
+        let err = gloss.run("not base64!", None).await.unwrap_err();
+        assert!(format!("{:#}", err).starts_with("gloss step 1 (base64)"));
+
+        // Plain `hex` passes records through as written
+        let gloss: GlossConfig = toml::from_str("transform = \"hex\"").unwrap();
+        assert_eq!(gloss.run("DE AD zz", None).await.unwrap(), "DEADzz");
+    }
+
+    #[tokio::test]
+    async fn test_gloss_segments() {
+        let gloss = |mode: &str| {
+            let config = format!(
+                r#"transform = "base64"
+segment = '@(\S+)'
+segments = "{}"
+cache = false"#,
+                mode
+            );
+            CompiledGloss::new(toml::from_str(&config).unwrap()).unwrap()
+        };
+        let line = "got @aGk= and @eW8= ok";
+        let ok =
+            |results: Glossed| -> Vec<String> { results.into_iter().map(|r| r.unwrap()).collect() };
+
+        assert_eq!(ok(gloss("first").apply(line).await), ["6869"]);
+        assert_eq!(
+            ok(gloss("inline").apply(line).await),
+            ["got @aGk=(6869) and @eW8=(796f) ok"]
+        );
+        assert_eq!(
+            ok(gloss("inline").apply("no serials").await),
+            ["no serials"]
+        );
+        assert_eq!(ok(gloss("rows").apply(line).await), ["6869", "796f"]);
+        assert_eq!(ok(gloss("rows").apply("no serials").await), ["no serials"]);
+        assert_eq!(
+            ok(gloss("inline").apply("bad @!! then @aGk=").await),
+            ["bad @!! then @aGk=(6869)"]
+        );
+        assert!(gloss("rows").apply("bad @!! then @aGk=").await[0].is_err());
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let data: Vec<u8> = (0..=255u8).rev().collect();
+        for charset in [
+            base85_charsets::ASCII85,
+            base85_charsets::Z85,
+            base85_charsets::BL4,
+        ] {
+            for len in 0..=9 {
+                let encoded = base85_charsets::encode(&data[..len], charset);
+                assert_eq!(
+                    encoded.len(),
+                    len / 4 * 5 + len % 4 + (len % 4 > 0) as usize
+                );
+                assert_eq!(
+                    base85_charsets::decode(&encoded, charset).unwrap(),
+                    &data[..len]
+                );
+            }
+        }
+        assert_eq!(
+            base85_charsets::encode(b"Hello", base85_charsets::ASCII85),
+            "87cURDZ"
+        );
+
+        let gloss: GlossConfig = toml::from_str(
+            r#"
+[[steps]]
+transform = "base64"
+
+[[steps]]
+transform = "hex_decode"
+"#,
+        )
+        .unwrap();
+        assert_eq!(gloss.decode("Njg2NTZjNmM2Zg==").unwrap(), b"hello");
+        assert_eq!(gloss.encode(b"hello").unwrap(), "Njg2NTZjNmM2Zg==");
+
+        let gloss: GlossConfig = toml::from_str(
+            r#"
+xor_key = "4b 21"
+
+[[steps]]
+transform = "hex_decode"
+
+[[steps]]
+transform = "xor_rolling"
+
+[[steps]]
+transform = "xor"
+xor_key = "ff"
+"#,
+        )
+        .unwrap();
+        // Rolling: 'h' ^ 4b ^ ff, 'e' ^ 21 ^ ff, then each byte ^ the encoded byte two back
+        let encoded = gloss.encode(b"hello").unwrap();
+        assert_eq!(&encoded[..4], "dcbb");
+        assert_eq!(gloss.decode(&encoded).unwrap(), b"hello");
+
+        let gloss: GlossConfig = toml::from_str("transform = \"zlib\"").unwrap();
+        assert!(gloss.encode(b"hello").is_err());
+        let gloss: GlossConfig = toml::from_str("transform = \"xor\"").unwrap();
+        assert!(gloss.decode("ab").is_err());
+    }
+
+    #[test]
+    fn test_text_encodings() {
+        let cases: &[(&str, &str, &[u8])] = &[
+            ("base32", "JBSWY3DPEHPK3PXP", b"Hello!\xde\xad\xbe\xef"),
+            ("base58", "StV1DL6CwTryKyV", b"hello world"),
+            ("base91", "fPNKd", b"test"),
+            ("base64url", "-_8", b"\xfb\xff"),
+            ("url", "caf%C3%A9%20au%2Flait", "café au/lait".as_bytes()),
+            (
+                "quoted_printable",
+                "caf=C3=A9 au lait",
+                "café au lait".as_bytes(),
+            ),
+        ];
+        for &(transform, encoded, decoded) in cases {
+            let gloss: GlossConfig =
+                toml::from_str(&format!("transform = {:?}", transform)).unwrap();
+            assert_eq!(gloss.decode(encoded).unwrap(), decoded, "{}", transform);
+            assert_eq!(gloss.encode(decoded).unwrap(), encoded, "{}", transform);
+        }
+
+        // Forgiving input: lowercase, grouped, padded base32 and padded base64url
+        let gloss: GlossConfig = toml::from_str("transform = \"base32\"").unwrap();
+        assert_eq!(
+            gloss.decode("jbsw y3dp ehpk 3pxp").unwrap(),
+            b"Hello!\xde\xad\xbe\xef"
+        );
+        let gloss: GlossConfig = toml::from_str("transform = \"base64url\"").unwrap();
+        assert_eq!(gloss.decode("-_8=").unwrap(), b"\xfb\xff");
+
+        let data: Vec<u8> = (0..=255u8).rev().collect();
+        for len in 0..40 {
+            let encoded = base91::encode(&data[..len]);
+            assert_eq!(base91::decode(&encoded).unwrap(), &data[..len]);
+        }
+        assert!(base91::decode("a b").is_err());
+    }
+
+    #[test]
+    fn test_decompress() {
+        use std::io::Write;
+
+        let text: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| i.wrapping_mul(i).to_le_bytes())
+            .collect();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&text).unwrap();
+        let gz = gz.finish().unwrap();
+        let info =
+            lz4_flex::frame::FrameInfo::new().block_size(lz4_flex::frame::BlockSize::Max64KB);
+        let mut lz4 = lz4_flex::frame::FrameEncoder::with_frame_info(info, Vec::new());
+        lz4.write_all(&text).unwrap();
+        let lz4 = lz4.finish().unwrap();
+        let zstd = zstd::encode_all(&text[..], 0).unwrap();
+
+        for (format, data) in [("gzip", gz), ("lz4", lz4), ("zstd", zstd)] {
+            let full = decompress(format, &data).unwrap();
+            assert_eq!(full.bytes, text, "{}", format);
+            assert!(full.truncated.is_none());
+
+            // Cut the stream in half: the recovered prefix is kept and marked
+            // (LZ4 only recovers whole blocks, hence the long input)
+            let partial = decompress(format, &data[..data.len() / 2]).unwrap();
+            assert!(partial.truncated.is_some(), "{}", format);
+            assert!(text.starts_with(&partial.bytes));
+            assert!(partial.display().starts_with("[truncated: "));
+        }
+
+        assert!(decompress("zlib", b"not compressed").is_err());
+
+        let bomb = zstd::encode_all(&vec![0; MAX_DECOMPRESSED as usize + 1][..], 0).unwrap();
+        let err = decompress("zstd", &bomb).unwrap_err();
+        assert!(err.to_string().contains("output exceeds"));
+    }
+
+    #[test]
+    fn test_decode_jwt() {
+        // The jwt.io example token, signed with "your-256-bit-secret"
+        let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                     eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiaWF0IjoxNTE2MjM5MDIyfQ.\
+                     SflKxwRJSMeKKF2QT4fwpMeJf36POk6yJV_adQssw5c";
+
+        let out = decode_jwt(token, None).unwrap();
+        assert!(out.starts_with("header: {\n  \"alg\": \"HS256\""));
+        assert!(out.contains("\n  \"name\": \"John Doe\""));
+        assert!(out.contains("\nsub: 1234567890\niat: 2018-01-18T01:30:22Z\n"));
+        assert!(out.ends_with("signature: unverified (HS256)"));
+
+        let out = decode_jwt(token, Some(b"your-256-bit-secret")).unwrap();
+        assert!(out.ends_with("signature: valid (HS256)"));
+        let out = decode_jwt(token, Some(b"wrong")).unwrap();
+        assert!(out.ends_with("signature: INVALID (HS256)"));
+
+        assert!(decode_jwt("eyJhbGciOiJub25lIn0", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gloss_queue_keeps_order() {
+        let gloss: GlossConfig = toml::from_str(
+            r#"command = ["sh", "-c", "sleep $0; echo done $0"]
+cache = false"#,
+        )
+        .unwrap();
+        let gloss = Arc::new(CompiledGloss::new(gloss).unwrap());
+
+        // Later records finish first, but come back in input order
+        let started = std::time::Instant::now();
+        let mut queue = GlossQueue::new(4);
+        let mut results = Vec::new();
+        for (i, delay) in ["0.3", "0.2", "", "0.1"].into_iter().enumerate() {
+            let job = (!delay.is_empty()).then(|| (gloss.clone(), delay.to_string()));
+            if let Some(done) = queue.push(i, job).await.unwrap() {
+                results.push(done);
+            }
+        }
+        while let Some(done) = queue.pop().await.unwrap() {
+            results.push(done);
+        }
+        assert!(started.elapsed() < std::time::Duration::from_millis(550));
+
+        let results: Vec<_> = results
+            .into_iter()
+            .map(|(i, result)| (i, result.map(|r| r.into_iter().next().unwrap().unwrap())))
+            .collect();
+        assert_eq!(
+            results,
+            [
+                (0, Some("done 0.3".to_string())),
+                (1, Some("done 0.2".to_string())),
+                (2, None),
+                (3, Some("done 0.1".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compiled_preset() {
+        let preset: Preset = toml::from_str(
+            r#"
+[preset]
+name = "test"
+
+[[detect]]
+type = "regex"
+pattern = "^id=\\d+"
+
+[[color]]
+match = "Epic"
+style = "magenta bold"
+
+[[fields]]
+name = "id"
+pattern = "id=(\\d+)"
+
+[[fields]]
+name = "rarity"
+from_gloss = true
+pattern = "Rarity: (\\w+)"
+"#,
+        )
+        .unwrap();
+        let compiled = CompiledPreset::new(preset).unwrap();
+
+        assert!(compiled.matches(b"id=42"));
+        assert!(!compiled.matches(b"id=x"));
+        assert_eq!(
+            compiled.columns("id=42", Some("Rarity: Epic")),
+            vec![
+                ("id".to_string(), "42".to_string()),
+                ("rarity".to_string(), "Epic".to_string())
+            ]
+        );
+        assert_eq!(
+            compiled.columns("id=42", None),
+            vec![("id".to_string(), "42".to_string())]
+        );
+        assert_eq!(
+            compiled.field_value("rarity", "id=42", "Rarity: Epic"),
+            Some("Epic".to_string())
+        );
+        assert_eq!(
+            compiled.color_spans("Rarity: Epic"),
+            vec![(8..12, "magenta bold")]
+        );
+
+        let bad: Preset =
+            toml::from_str("[[detect]]\ntype = \"regex\"\npattern = \"(\"\n").unwrap();
+        assert!(CompiledPreset::new(bad).is_err());
+        let bad: Preset = toml::from_str("[[color]]\nmatch = \"(\"\nstyle = \"red\"\n").unwrap();
+        assert!(CompiledPreset::new(bad).is_err());
+    }
+
+    #[test]
+    fn test_raw_and_gloss_fields() {
+        let preset: Preset = toml::from_str(
+            r#"
+[preset]
+name = "mixed"
+
+[gloss]
+transform = "none"
+
+[[fields]]
+name = "decoded"
+from_gloss = true
+pattern = "v=(\\w+)"
+
+[[fields]]
+name = "serial"
+pattern = "v=(\\w+)"
+
+[[fields]]
+name = "tail"
+from_gloss = true
+pattern = "(\\w+)$"
+"#,
+        )
+        .unwrap();
+        let compiled = CompiledPreset::new(preset).unwrap();
+
+        // Same patterns, different sources: raw fields read the record as given
+        assert_eq!(
+            compiled.columns("v=raw", Some("v=decoded x")),
+            vec![
+                ("decoded".to_string(), "decoded".to_string()),
+                ("serial".to_string(), "raw".to_string()),
+                ("tail".to_string(), "x".to_string())
+            ]
+        );
+        // Without gloss output only raw fields are emitted
+        assert_eq!(
+            compiled.columns("v=raw", None),
+            vec![("serial".to_string(), "raw".to_string())]
+        );
+        assert_eq!(
+            compiled.field_value("serial", "v=raw", "v=decoded"),
+            Some("raw".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preset_scripts() {
+        let preset: Preset = toml::from_str(
+            r#"
+[preset]
+name = "scripted"
+
+[[detect]]
+type = "script"
+script = "bytes.len() > 2 && bytes[0] == 0x21"
+
+[gloss]
+cache = false
+transform_script = """
+let out = blob();
+for i in 0..data.len() { out.push(data[i] ^ 0x20); }
+out.as_string()
+"""
+
+[[fields]]
+name = "upper"
+from_gloss = true
+pattern = "(.*)"
+
+[[fields]]
+name = "length"
+script = "fields.upper.len() + record.len()"
+"#,
+        )
+        .unwrap();
+        let compiled = CompiledPreset::new(preset).unwrap();
+
+        assert!(compiled.matches(b"!ab"));
+        assert!(!compiled.matches(b"ab!"));
+
+        let gloss = compiled.gloss.as_ref().unwrap();
+        let glossed = gloss.apply("!ab").await.remove(0).unwrap();
+        assert_eq!(glossed, "\x01AB");
+        assert_eq!(
+            compiled.field_value("length", "!ab", &glossed).unwrap(),
+            "6"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preset_plugins() {
+        let dir = tempfile::tempdir().unwrap();
+        // Answers every record with the same JSON
+        let wasm = wat::parse_str(
+            r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{\"level\":7}")
+  (func (export "alloc") (param i32) (result i32) (i32.const 64))
+  (func (export "transform") (param i32 i32) (result i64) (i64.const 11)))
+"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("level.wasm"), wasm).unwrap();
+        let path = dir.path().join("plugged.toml");
+        fs::write(
+            &path,
+            r#"
+[preset]
+name = "plugged"
+
+[gloss]
+cache = false
+wasm = "level.wasm"
+output = "json"
+
+[[fields]]
+name = "level"
+from_gloss = true
+json = "$.level"
+
+[[fields]]
+name = "raw"
+wasm = "level.wasm"
+"#,
+        )
+        .unwrap();
+
+        let mut mgr = PresetManager::default();
+        mgr.load_preset(&path).unwrap();
+        let compiled = mgr.compiled("plugged").unwrap();
+        let gloss = compiled.gloss.as_ref().unwrap();
+        assert_eq!(
+            gloss.config.wasm.as_deref(),
+            Some(&*dir.path().join("level.wasm"))
+        );
+
+        let glossed = gloss.apply("anything").await.remove(0).unwrap();
+        assert_eq!(glossed, r#"{"level":7}"#);
+        assert_eq!(
+            compiled.columns("anything", Some(&glossed)),
+            [
+                ("level".to_string(), "7".to_string()),
+                ("raw".to_string(), glossed.clone())
+            ]
+        );
+        assert!(gloss.config.encode(b"x").is_err());
+    }
+
+    #[test]
+    fn test_json_fields() {
+        let preset: Preset = toml::from_str(
+            r#"
+[preset]
+name = "items"
+
+[gloss]
+transform = "none"
+output = "json"
+
+[[fields]]
+name = "rarity"
+from_gloss = true
+json = "$.item.rarity"
+
+[[fields]]
+name = "second"
+from_gloss = true
+json = "$.parts[1]['odd key']"
+"#,
+        )
+        .unwrap();
+        let mut compiled = CompiledPreset::new(preset).unwrap();
+        let glossed = r#"{"item":{"rarity":"Epic","level":7},"parts":[null,{"odd key":[1,2]}]}"#;
+        let values = |glossed| -> Vec<String> {
+            (compiled.columns("", Some(glossed)).into_iter())
+                .map(|(_, value)| value)
+                .collect()
+        };
+        assert_eq!(values(glossed), vec!["Epic", "[1,2]"]);
+        assert_eq!(values("not json"), vec!["", ""]);
+
+        // Without JSON fields, columns are the top-level values in order
+        compiled.preset.fields.clear();
+        compiled.fields.clear();
+        let columns = compiled.columns("", Some(r#"{"b":"x","a":2,"c":null}"#));
+        let pairs: Vec<_> = (columns.iter())
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("b", "x"), ("a", "2"), ("c", "")]);
+
+        // Later records keep the first record's columns
+        let columns = compiled.columns("", Some(r#"{"a":3,"d":4}"#));
+        let pairs: Vec<_> = (columns.iter())
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("b", ""), ("a", "3"), ("c", "")]);
+
+        assert!(JsonPath::parse("item.rarity").is_err());
+        assert!(JsonPath::parse("$.a[x]").is_err());
+        assert!(JsonPath::parse("$")
+            .unwrap()
+            .select(&serde_json::json!(3))
+            .is_some());
+    }
+
+    #[test]
+    fn test_preset_inheritance() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| fs::write(dir.path().join(name), content).unwrap();
+        write(
+            "base.toml",
+            r#"
+[preset]
+name = "items"
+description = "Base"
+
+[[detect]]
+type = "starts_with"
+value = "@Ug"
+
+[gloss]
+transform = "base85"
+base85_charset = "ascii85"
+
+[[fields]]
+name = "rarity"
+from_gloss = true
+pattern = "Rarity: (\\w+)"
+"#,
+        );
+        write(
+            "v2.toml",
+            r#"
+[preset]
+name = "items-v2"
+extends = "items"
+
+[[detect]]
+type = "min_length"
+value = 20
+
+[gloss]
+base85_charset = "bl4"
+
+[[fields]]
+name = "rarity"
+pattern = "R=(\\w+)"
+
+[[fields]]
+name = "level"
+pattern = "L=(\\d+)"
+"#,
+        );
+        write(
+            "cmd.toml",
+            r#"
+[preset]
+name = "items-cmd"
+extends = "items"
+
+[gloss]
+command = ["cat"]
+"#,
+        );
+        write("loop-a.toml", "[preset]\nname = \"a\"\nextends = \"b\"\n");
+        write("loop-b.toml", "[preset]\nname = \"b\"\nextends = \"a\"\n");
+
+        let mut mgr = PresetManager::default();
+        mgr.load_from_dir(dir.path()).unwrap();
+        mgr.resolve_inheritance();
+
+        let child = mgr.get("items-v2").unwrap();
+        assert_eq!(child.preset.name, "items-v2");
+        assert!(child.preset.description.is_empty());
+        assert_eq!(child.detect.len(), 2);
+        let gloss = child.gloss.as_ref().unwrap();
+        assert_eq!(gloss.transform.as_deref(), Some("base85"));
+        assert_eq!(gloss.base85_charset.as_deref(), Some("bl4"));
+        assert_eq!(child.fields.len(), 2);
+        assert_eq!(child.fields[0].pattern, "R=(\\w+)");
+        assert!(child.fields[0].from_gloss);
+
+        let gloss = mgr.get("items-cmd").unwrap().gloss.as_ref().unwrap();
+        assert_eq!(gloss.command, Some(vec!["cat".to_string()]));
+        assert_eq!(gloss.transform, None);
+
+        assert!(mgr.resolve_source("a", &mut Vec::new()).is_err());
+        assert!(mgr.get("a").is_none());
+        assert!(mgr.get("b").is_none());
+    }
+
+    #[test]
+    fn test_layout_fields() {
+        let toml = r#"
+[preset]
+name = "save"
+
+[[layout]]
+name = "id"
+offset = 0
+type = "u16le"
+
+[[layout]]
+name = "owner"
+offset = 2
+type = "utf16le:u8len"
+
+[[layout]]
+name = "tag"
+offset = 7
+length = 4
+type = "ascii:fixed"
+"#;
+
+        let preset: Preset = toml::from_str(toml).unwrap();
+        let bytes = [0x39, 0x05, 2, b'o', 0, b'k', 0, b'A', b'B', 0, 0];
+        assert_eq!(
+            preset.decode_layout(&bytes),
+            vec!["1337", "\"ok\"", "\"AB\""]
+        );
+
+        let huge = LayoutField {
+            name: "huge".into(),
+            offset: usize::MAX,
+            data_type: "hex".into(),
+            length: Some(4),
+        };
+        assert_eq!(huge.decode(&bytes), "");
+    }
 }
@@ -0,0 +1,116 @@
+//! Embedded Rhai scripts for presets
+//!
+//! `transform_script` gloss steps, `script` detect rules and `script` fields
+//! are small Rhai programs run with the record in scope; the value of the last
+//! expression is the result. Scripts are sandboxed: `import` can't load files,
+//! `eval` is disabled, nothing beyond Rhai's core packages is registered (so
+//! there is no filesystem, network or process access) and each run is stopped
+//! after a fixed number of operations, so a runaway loop fails the record
+//! instead of hanging. `print` and `debug` write to stderr.
+
+use anyhow::{Context, Result};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, Scope, AST};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Operations a single run may take before it is stopped
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// A compiled script
+#[derive(Debug)]
+pub struct Script {
+    ast: AST,
+}
+
+/// The sandboxed engine shared by every script
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(64)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(16 << 20)
+            .set_max_array_size(1 << 20)
+            .set_max_map_size(1 << 16)
+            .on_print(|text| eprintln!("{}", text))
+            .on_debug(|text, _, pos| eprintln!("[script {}] {}", pos, text));
+        engine
+    })
+}
+
+/// Compile a script, reusing an earlier compile of the same source
+pub fn compile(source: &str) -> Result<Arc<Script>> {
+    static COMPILED: OnceLock<Mutex<HashMap<String, Arc<Script>>>> = OnceLock::new();
+    let compiled = COMPILED.get_or_init(Default::default);
+    if let Some(script) = compiled.lock().unwrap().get(source) {
+        return Ok(script.clone());
+    }
+    let ast = engine()
+        .compile(source)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .context("invalid script")?;
+    let script = Arc::new(Script { ast });
+    (compiled.lock().unwrap()).insert(source.to_string(), script.clone());
+    Ok(script)
+}
+
+impl Script {
+    /// Run the script with `vars` in scope
+    pub fn eval(&self, vars: Vec<(&str, Dynamic)>) -> Result<Dynamic> {
+        let mut scope = Scope::new();
+        for (name, value) in vars {
+            scope.push_dynamic(name, value);
+        }
+        engine()
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| anyhow::anyhow!("script error: {}", e))
+    }
+}
+
+/// A script result as text: strings as-is, `()` empty, blobs as hex, and maps,
+/// arrays and numbers as compact JSON
+pub fn to_text(value: Dynamic) -> String {
+    if value.is_unit() {
+        return String::new();
+    }
+    if value.is_string() {
+        return value.into_string().unwrap_or_default();
+    }
+    if value.is_blob() {
+        return hex::encode(value.into_blob().unwrap_or_default());
+    }
+    rhai::serde::from_dynamic::<serde_json::Value>(&value)
+        .map_or_else(|_| value.to_string(), |json| json.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts() {
+        let reverse = compile("let out = data; out.reverse(); out").unwrap();
+        let value = reverse.eval(vec![("data", Dynamic::from_blob(vec![1, 2, 3]))]);
+        assert_eq!(value.unwrap().into_blob().unwrap(), [3, 2, 1]);
+
+        let map = compile(r#"#{ level: record.len(), name: "x" }"#).unwrap();
+        let value = map.eval(vec![("record", "abcd".into())]).unwrap();
+        assert_eq!(to_text(value), r#"{"level":4,"name":"x"}"#);
+
+        // Sandboxed: no modules, no eval, no endless loops
+        assert!(compile(r#"import "std" as s; 1"#)
+            .unwrap()
+            .eval(vec![])
+            .is_err());
+        assert!(compile(r#"eval("1")"#).is_err());
+        let spin = compile("loop {}").unwrap();
+        assert!(spin.eval(vec![]).is_err());
+
+        assert!(compile("let = ;").is_err());
+    }
+}